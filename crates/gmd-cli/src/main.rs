@@ -1,28 +1,867 @@
-use clap::{Parser, Subcommand};
-use eyre::{Result, WrapErr};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use eyre::{eyre, Result, WrapErr};
 use gmd_parser::{
-    calculator::{GMDDay, GMDSummary},
-    models::{GMDLog, LogEntry, StartDay},
-    parser::FromGMD,
+    calculator::{
+        GMDDay, GMDEaten, GMDMeal, GMDSummary, IngredientChange, ProductVersion, StrictMode,
+        SummaryOptions, TargetProgress, TargetStatus,
+    },
+    config::{Config, DisplayUnit},
+    formatter,
+    models::{
+        AmountOf, Kcal, Kilojoule, LogEntry, Meal, Nutrient, ProductDefinition, ProductName,
+        Quantity, Spanned, StartDay, UnitOfMeasure,
+    },
+    parser::ToGMD,
+    workspace::{Source, Workspace},
 };
 use itertools::Itertools;
-use std::{iter::once, path::PathBuf};
+use serde::Serialize;
+use std::{collections::BTreeMap, iter::once, path::PathBuf};
 use tap::prelude::*;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Commands>,
+    /// without a subcommand the files are summarized
+    #[command(flatten)]
+    input: Input,
+    #[command(flatten)]
+    units: Units,
+}
+
+#[derive(Args)]
+struct Input {
+    /// files, directories searched for `*.gmd` files, glob patterns or `-` for stdin.
+    /// the inputs of the config when none are given
     files: Vec<PathBuf>,
+    /// fail on products which can't be broken down into their ingredients,
+    /// instead of counting them as nutrients. `--strict=false` overrides the config
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    strict: Option<bool>,
+    /// day to file meals and products eaten before the first day under,
+    /// `today` for the current one. without it they are an error
+    #[arg(long, value_parser = parse_date)]
+    date: Option<chrono::NaiveDate>,
+    /// logs coming from the config, such as its targets
+    #[arg(skip)]
+    configured: Vec<Source>,
+}
+
+fn parse_date(date: &str) -> Result<chrono::NaiveDate> {
+    match date {
+        "today" => chrono::Local::now().date_naive().pipe(Ok),
+        date => date.parse().wrap_err("expected YYYY-MM-DD or today"),
+    }
+}
+
+#[derive(Args, Clone)]
+struct Units {
+    /// unit energy is reported in, as logged or configured when not set
+    #[arg(long, value_enum)]
+    energy: Option<EnergyUnit>,
+    #[arg(skip)]
+    nutrients: BTreeMap<ProductName, DisplayUnit>,
+    /// nutrients shown first
+    #[arg(skip)]
+    columns: Vec<ProductName>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum EnergyUnit {
+    Kcal,
+    Kj,
 }
 
 #[derive(Subcommand)]
 enum Commands {
-    /// does testing things
-    Test {
-        /// lists test values
-        #[arg(short, long)]
-        list: bool,
+    /// prints a table of eaten nutrients for every day
+    Summary {
+        #[command(flatten)]
+        input: Input,
+        #[command(flatten)]
+        units: Units,
+    },
+    /// prints a table of eaten nutrients for every meal, followed by the day's total
+    Meals {
+        #[command(flatten)]
+        input: Input,
+        #[command(flatten)]
+        units: Units,
+    },
+    /// prints every eat entry in the order of the day, with running totals of eaten nutrients
+    Timeline {
+        #[command(flatten)]
+        input: Input,
+        #[command(flatten)]
+        units: Units,
+    },
+    /// compares eaten nutrients with the targets in effect on every day
+    Targets {
+        #[command(flatten)]
+        input: Input,
+        #[command(flatten)]
+        units: Units,
+    },
+    /// validates log files without printing the summary
+    Check {
+        #[command(flatten)]
+        input: Input,
+    },
+    /// reformats log files in place
+    Fmt {
+        /// only report files which are not formatted, exits with an error if there are any
+        #[arg(long)]
+        check: bool,
+        files: Vec<PathBuf>,
     },
+    /// lists defined products together with the day they were defined on
+    Products {
+        #[command(flatten)]
+        input: Input,
+    },
+    /// lists every definition of the products with the days they were in effect on,
+    /// and how each of them changed the ingredients of the previous one
+    Versions {
+        /// only show this product
+        #[arg(long)]
+        product: Option<String>,
+        /// changes of an ingredient by more than this many percent are marked with `!`
+        #[arg(long, default_value_t = rust_decimal::Decimal::TEN)]
+        threshold: rust_decimal::Decimal,
+        #[command(flatten)]
+        input: Input,
+    },
+    /// writes eaten nutrients for every day in a machine readable format
+    Export {
+        /// json, unless configured otherwise
+        #[arg(long, value_enum)]
+        format: Option<ExportFormat>,
+        #[command(flatten)]
+        input: Input,
+        #[command(flatten)]
+        units: Units,
+    },
+    /// prints total and daily average of every nutrient across all days
+    Totals {
+        #[command(flatten)]
+        input: Input,
+        #[command(flatten)]
+        units: Units,
+    },
+}
+
+impl Commands {
+    /// fills in whatever wasn't given on the command line from the config
+    fn configure(mut self, config: &Config) -> Result<Self> {
+        match &mut self {
+            Commands::Summary { input, units }
+            | Commands::Meals { input, units }
+            | Commands::Timeline { input, units }
+            | Commands::Targets { input, units }
+            | Commands::Totals { input, units } => input
+                .configure(config)
+                .and_then(|_| units.configure(config)),
+            Commands::Export {
+                format,
+                input,
+                units,
+            } => input
+                .configure(config)
+                .and_then(|_| units.configure(config))
+                .and_then(|_| match (*format, &config.format) {
+                    (None, Some(configured)) => ExportFormat::from_str(configured, true)
+                        .map_err(|message| eyre!("export format: {message}"))
+                        .map(|configured| *format = Some(configured)),
+                    _ => Ok(()),
+                }),
+            Commands::Check { input }
+            | Commands::Products { input }
+            | Commands::Versions { input, .. } => input.configure(config),
+            Commands::Fmt { .. } => Ok(()),
+        }
+        .map(|_| self)
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    Json,
+    Yaml,
+}
+
+impl Input {
+    fn configure(&mut self, config: &Config) -> Result<()> {
+        config.source().map(|source| {
+            if self.files.is_empty() {
+                self.files = config.inputs.clone();
+            }
+            self.strict.get_or_insert(config.strict);
+            self.configured = source.into_iter().collect();
+        })
+    }
+
+    fn sources(&self) -> Result<Vec<Source>> {
+        self.files
+            .iter()
+            .map(|path| match path.to_str() {
+                Some("-") => std::io::read_to_string(std::io::stdin())
+                    .wrap_err("reading stdin")
+                    .map(|contents| Source::Memory {
+                        name: "<stdin>".into(),
+                        contents,
+                    }),
+                Some(pattern) if pattern.contains(['*', '?']) => {
+                    Source::Glob(pattern.into()).pipe(Ok)
+                }
+                _ => Source::Path(path.clone()).pipe(Ok),
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(|sources| self.configured.iter().cloned().chain(sources).collect())
+    }
+
+    /// reads, parses and merges all the files
+    fn load(&self) -> Result<Workspace> {
+        self.sources().and_then(Workspace::load)
+    }
+
+    fn options(&self) -> SummaryOptions {
+        SummaryOptions {
+            strict: match self.strict.unwrap_or_default() {
+                true => StrictMode::Strict,
+                false => StrictMode::Lenient,
+            },
+            reference_date: self.date,
+        }
+    }
+}
+
+impl Units {
+    fn configure(&mut self, config: &Config) -> Result<()> {
+        config.units().map(|nutrients| {
+            self.nutrients = nutrients;
+            self.columns = config.columns();
+        })
+    }
+
+    /// energy from the command line wins over the unit configured for `product`
+    fn convert(&self, product: &ProductName, quantity: Quantity) -> Shown {
+        self.energy
+            .map(|energy| match energy {
+                EnergyUnit::Kcal => DisplayUnit::from(UnitOfMeasure::from(Kcal)),
+                EnergyUnit::Kj => DisplayUnit::from(UnitOfMeasure::from(Kilojoule)),
+            })
+            .and_then(|unit| unit.convert(quantity).map(|amount| (amount, unit)))
+            .or_else(|| {
+                self.nutrients
+                    .get(product)
+                    .and_then(|unit| unit.convert(quantity).map(|amount| (amount, *unit)))
+            })
+            .unwrap_or((quantity.amount, quantity.unit.into()))
+            .pipe(|(amount, unit)| Shown {
+                amount: amount.normalize(),
+                unit: unit.to_string(),
+            })
+    }
+
+    /// like [`Units::convert`], but every quantity is rounded, converted or not
+    fn show(&self, product: &ProductName, quantity: Quantity) -> Shown {
+        self.convert(product, quantity).pipe(|shown| Shown {
+            amount: Self::round(shown.amount),
+            ..shown
+        })
+    }
+
+    /// two decimal places, or two significant digits for amounts too small for them
+    fn round(amount: rust_decimal::Decimal) -> rust_decimal::Decimal {
+        match amount.abs() < rust_decimal::Decimal::ONE {
+            true => amount.round_sf(2).unwrap_or(amount),
+            false => amount.round_dp(2),
+        }
+        .normalize()
+    }
+}
+
+/// a quantity in the unit it's shown in
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct Shown {
+    amount: rust_decimal::Decimal,
+    unit: String,
+}
+
+impl std::fmt::Display for Shown {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.amount, self.unit)
+    }
+}
+
+fn table(rows: impl IntoIterator<Item = Vec<String>>) -> String {
+    rows.into_iter()
+        .collect_vec()
+        .pipe(tabled::tables::IterTable::new)
+        .to_string()
+}
+
+/// products eaten on any of the days, the configured columns first and then the most eaten.
+/// once any nutrients are declared only those are tracked
+fn tracked_products<'summary>(
+    summary: &'summary GMDSummary,
+    units: &Units,
+) -> Vec<&'summary ProductName> {
+    let nutrients = summary.nutrients();
+    summary
+        .0
+        .values()
+        .flat_map(|day| day.state.iter())
+        .filter(|(name, _)| nutrients.is_empty() || nutrients.contains(name))
+        .sorted_unstable_by_key(|(_, quantity)| quantity.amount)
+        .rev()
+        .map(|(name, _)| name)
+        .unique_by(|name| *name)
+        .sorted_by_key(|name| {
+            units
+                .columns
+                .iter()
+                .position(|column| column == *name)
+                .unwrap_or(units.columns.len())
+        })
+        .collect_vec()
+}
+
+fn summary(workspace: &Workspace, units: &Units, options: SummaryOptions) -> Result<()> {
+    workspace.summary(options).map(|summary| {
+        tracked_products(&summary, units)
+            .pipe(|tracked_products| {
+                once(
+                    once("day".to_string())
+                        .chain(tracked_products.iter().map(|name| name.to_string()))
+                        .collect_vec(),
+                )
+                .chain(
+                    summary
+                        .0
+                        .iter()
+                        .map(|(day, GMDDay { state, .. })| {
+                            once(day.to_string())
+                                .chain(tracked_products.iter().map(|product| {
+                                    state
+                                        .get(product)
+                                        .copied()
+                                        .map(|v| units.show(product, v).to_string())
+                                        .unwrap_or_else(|| "~".into())
+                                }))
+                                .collect_vec()
+                        })
+                        .collect_vec(),
+                )
+                .pipe(table)
+            })
+            .pipe(|table| println!("{table}"));
+        unknown_composition(&summary, units)
+            .into_iter()
+            .for_each(|table| println!("unknown composition:\n{table}"));
+    })
+}
+
+/// products left over on each day which aren't declared nutrients
+fn unknown_composition(summary: &GMDSummary, units: &Units) -> Option<String> {
+    summary
+        .0
+        .keys()
+        .flat_map(|day| {
+            summary
+                .unknown_composition(*day)
+                .into_iter()
+                .map(move |(product, quantity)| {
+                    vec![
+                        day.to_string(),
+                        product.to_string(),
+                        units.show(product, quantity).to_string(),
+                    ]
+                })
+        })
+        .collect_vec()
+        .pipe(
+            |rows| match summary.nutrients().is_empty() || rows.is_empty() {
+                true => None,
+                false => once(["day", "product", "eaten"].map(String::from).to_vec())
+                    .chain(rows)
+                    .pipe(table)
+                    .pipe(Some),
+            },
+        )
+}
+
+fn meals(workspace: &Workspace, units: &Units, options: SummaryOptions) -> Result<()> {
+    workspace.summary(options).map(|summary| {
+        tracked_products(&summary, units)
+            .pipe(|tracked_products| {
+                let row =
+                    |day: String, meal: Option<&Meal>, state: &BTreeMap<ProductName, Quantity>| {
+                        [
+                            day,
+                            meal.map(|meal| meal.name.clone())
+                                .unwrap_or_else(|| "total".into()),
+                            meal.and_then(|meal| meal.time)
+                                .map(|time| time.to_gmd())
+                                .unwrap_or_else(|| "~".into()),
+                        ]
+                        .into_iter()
+                        .chain(tracked_products.iter().map(|product| {
+                            state
+                                .get(product)
+                                .copied()
+                                .map(|v| units.show(product, v).to_string())
+                                .unwrap_or_else(|| "~".into())
+                        }))
+                        .collect_vec()
+                    };
+                once(
+                    ["day", "meal", "time"]
+                        .map(String::from)
+                        .into_iter()
+                        .chain(tracked_products.iter().map(|name| name.to_string()))
+                        .collect_vec(),
+                )
+                .chain(
+                    summary
+                        .0
+                        .iter()
+                        .flat_map(|(day, GMDDay { state, meals, .. })| {
+                            meals
+                                .iter()
+                                .map(|GMDMeal { meal, state }| {
+                                    row(day.to_string(), Some(meal), state)
+                                })
+                                .chain(once(row(day.to_string(), None, state)))
+                                .collect_vec()
+                        }),
+                )
+                .pipe(table)
+            })
+            .pipe(|table| println!("{table}"))
+    })
+}
+
+fn timeline(workspace: &Workspace, units: &Units, options: SummaryOptions) -> Result<()> {
+    workspace.summary(options).and_then(|summary| {
+        let tracked_products = tracked_products(&summary, units);
+        summary
+            .0
+            .iter()
+            .map(|(day, day_summary)| {
+                day_summary.timeline().map(|timeline| {
+                    timeline
+                        .into_iter()
+                        .map(|(GMDEaten { entry, .. }, total)| {
+                            [day.to_string(), entry.to_gmd()]
+                                .into_iter()
+                                .chain(tracked_products.iter().map(|product| {
+                                    total
+                                        .get(product)
+                                        .copied()
+                                        .map(|v| units.show(product, v).to_string())
+                                        .unwrap_or_else(|| "~".into())
+                                }))
+                                .collect_vec()
+                        })
+                        .collect_vec()
+                })
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(|rows| {
+                once(
+                    ["day", "entry"]
+                        .map(String::from)
+                        .into_iter()
+                        .chain(tracked_products.iter().map(|name| name.to_string()))
+                        .collect_vec(),
+                )
+                .chain(rows.into_iter().flatten())
+                .pipe(table)
+            })
+            .map(|table| println!("{table}"))
+    })
+}
+
+fn targets(workspace: &Workspace, units: &Units, options: SummaryOptions) -> Result<()> {
+    workspace.summary(options).and_then(|summary| {
+        summary
+            .0
+            .keys()
+            .map(|day| {
+                summary.progress(*day).map(|progress| {
+                    progress
+                        .into_iter()
+                        .map(
+                            |TargetProgress {
+                                 target,
+                                 eaten,
+                                 percent_met,
+                                 status,
+                             }| {
+                                vec![
+                                    day.to_string(),
+                                    target.product.to_string(),
+                                    target
+                                        .upper
+                                        .map(|upper| {
+                                            format!(
+                                                "{}..{}",
+                                                units.show(&target.product, target.lower),
+                                                units.show(&target.product, upper)
+                                            )
+                                        })
+                                        .unwrap_or_else(|| {
+                                            units.show(&target.product, target.lower).to_string()
+                                        }),
+                                    units.show(&target.product, eaten).to_string(),
+                                    format!("{}%", percent_met.round_dp(1)),
+                                    match status {
+                                        TargetStatus::Met => "met".to_string(),
+                                        TargetStatus::Deficit(deficit) => {
+                                            format!(
+                                                "deficit {}",
+                                                units.show(&target.product, deficit)
+                                            )
+                                        }
+                                        TargetStatus::Surplus(surplus) => {
+                                            format!(
+                                                "surplus {}",
+                                                units.show(&target.product, surplus)
+                                            )
+                                        }
+                                    },
+                                ]
+                            },
+                        )
+                        .collect_vec()
+                })
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(|rows| {
+                once(
+                    ["day", "product", "target", "eaten", "met", "status"]
+                        .map(String::from)
+                        .to_vec(),
+                )
+                .chain(rows.into_iter().flatten())
+                .pipe(table)
+            })
+            .map(|table| println!("{table}"))
+    })
+}
+
+/// reports every malformed entry in every file and every definition cycle
+/// before validating the merged log
+fn check(input: &Input) -> Result<()> {
+    input
+        .sources()
+        .and_then(Workspace::load_recovering)
+        .and_then(|(workspace, diagnostics)| {
+            let cycles = workspace.log.definition_cycles();
+            diagnostics.iter().for_each(|(file, diagnostic)| {
+                println!("{}: {diagnostic}\n", workspace.files[file.0].path.display())
+            });
+            cycles.iter().for_each(|Spanned { span, inner }| {
+                println!("{}: {inner}\n", workspace.location(span))
+            });
+            match diagnostics.len() + cycles.len() {
+                0 => workspace.summary(input.options()).map(|summary| {
+                    println!(
+                        "ok: {} entries, {} day(s)",
+                        workspace.log.0.len(),
+                        summary.0.len()
+                    )
+                }),
+                problems => Err(eyre!("{problems} problem(s) found")),
+            }
+        })
+}
+
+fn fmt(files: Vec<PathBuf>, check: bool) -> Result<()> {
+    files
+        .into_iter()
+        .map(|path| {
+            std::fs::read_to_string(&path)
+                .wrap_err("reading file")
+                .and_then(|contents| {
+                    formatter::format(&contents).and_then(|formatted| match formatted == contents {
+                        true => Ok(None),
+                        false if check => Ok(Some(path.clone())),
+                        false => std::fs::write(&path, formatted)
+                            .wrap_err("writing file")
+                            .map(|_| None),
+                    })
+                })
+                .with_context(|| format!("formatting '{}'", path.display()))
+        })
+        .collect::<Result<Vec<_>>>()
+        .map(|unformatted| unformatted.into_iter().flatten().collect_vec())
+        .and_then(|unformatted| match unformatted.is_empty() {
+            true => Ok(()),
+            false => unformatted
+                .tap(|unformatted| {
+                    unformatted
+                        .iter()
+                        .for_each(|path| println!("{}", path.display()))
+                })
+                .pipe(|unformatted| Err(eyre!("{} file(s) not formatted", unformatted.len()))),
+        })
+}
+
+fn products(workspace: &Workspace) -> Result<()> {
+    workspace
+        .log
+        .0
+        .iter()
+        .scan(None, |current_day, Spanned { span, inner: entry }| {
+            match entry {
+                LogEntry::StartDay(StartDay(day)) => {
+                    *current_day = Some(*day);
+                    None
+                }
+                LogEntry::Define(ProductDefinition {
+                    name,
+                    ingredients:
+                        AmountOf {
+                            quantity,
+                            inner: ingredients,
+                        },
+                    ..
+                }) => vec![
+                    name.to_string(),
+                    current_day
+                        .map(|day| day.to_string())
+                        .unwrap_or_else(|| "~".into()),
+                    quantity.to_gmd(),
+                    ingredients
+                        .as_ref()
+                        .map(|ingredients| ingredients.len().to_string())
+                        .unwrap_or_else(|| "~".into()),
+                    workspace.location(span),
+                ]
+                .pipe(Some),
+                LogEntry::Nutrient(Nutrient(name)) => vec![
+                    name.to_string(),
+                    current_day
+                        .map(|day| day.to_string())
+                        .unwrap_or_else(|| "~".into()),
+                    "~".into(),
+                    "nutrient".into(),
+                    workspace.location(span),
+                ]
+                .pipe(Some),
+                LogEntry::Include(_)
+                | LogEntry::Eat(_)
+                | LogEntry::Uneat(_)
+                | LogEntry::Meal(_)
+                | LogEntry::Target(_) => None,
+            }
+            .pipe(Some)
+        })
+        .flatten()
+        .pipe(|rows| {
+            once(
+                ["product", "defined on", "per", "ingredients", "source"]
+                    .map(String::from)
+                    .to_vec(),
+            )
+            .chain(rows)
+            .pipe(table)
+        })
+        .pipe(Ok)
+        .map(|table| println!("{table}"))
+}
+
+fn versions(
+    workspace: &Workspace,
+    product: Option<ProductName>,
+    threshold: rust_decimal::Decimal,
+    options: SummaryOptions,
+) -> Result<()> {
+    let show_change = |change: &IngredientChange| {
+        let show = |quantity: Option<Quantity>| {
+            quantity
+                .map(|quantity| quantity.to_gmd())
+                .unwrap_or_else(|| "~".into())
+        };
+        format!(
+            "{}{}: {} -> {}{}",
+            match change.exceeds(threshold / rust_decimal::Decimal::ONE_HUNDRED) {
+                true => "! ",
+                false => "",
+            },
+            change.product,
+            show(change.before),
+            show(change.after),
+            change
+                .relative()
+                .map(|relative| format!(
+                    " ({:+}%)",
+                    (relative * rust_decimal::Decimal::ONE_HUNDRED)
+                        .round_dp(2)
+                        .normalize()
+                ))
+                .unwrap_or_default()
+        )
+    };
+    workspace.summary(options).and_then(|summary| {
+        summary
+            .products()
+            .into_iter()
+            .filter(|name| {
+                product
+                    .as_ref()
+                    .map(|product| product == *name)
+                    .unwrap_or(true)
+            })
+            .flat_map(|name| {
+                summary.versions(name).pipe(|versions| {
+                    versions
+                        .iter()
+                        .enumerate()
+                        .map(
+                            |(
+                                index,
+                                ProductVersion {
+                                    definition,
+                                    span,
+                                    effective_from,
+                                    effective_until,
+                                    used_on,
+                                },
+                            )| {
+                                index
+                                    .checked_sub(1)
+                                    .and_then(|previous| versions.get(previous))
+                                    .map(|previous| previous.definition.diff(definition))
+                                    .transpose()
+                                    .map(|diff| {
+                                        vec![
+                                            name.to_string(),
+                                            (index + 1).to_string(),
+                                            effective_from
+                                                .map(|day| day.to_string())
+                                                .unwrap_or_else(|| "library".into()),
+                                            effective_until
+                                                .map(|day| day.to_string())
+                                                .unwrap_or_else(|| "~".into()),
+                                            used_on.iter().map(|day| day.to_string()).join("\n"),
+                                            span.map(|span| workspace.location(span))
+                                                .unwrap_or_else(|| "~".into()),
+                                            diff.map(|diff| {
+                                                diff.0.iter().map(show_change).join("\n")
+                                            })
+                                            .unwrap_or_default(),
+                                        ]
+                                    })
+                            },
+                        )
+                        .collect_vec()
+                })
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(|rows| {
+                once(
+                    [
+                        "product", "version", "from", "until", "used on", "source", "changes",
+                    ]
+                    .map(String::from)
+                    .to_vec(),
+                )
+                .chain(rows)
+                .pipe(table)
+            })
+            .map(|table| println!("{table}"))
+    })
+}
+
+fn export(
+    workspace: &Workspace,
+    format: Option<ExportFormat>,
+    units: &Units,
+    options: SummaryOptions,
+) -> Result<()> {
+    workspace
+        .summary(options)
+        .map(|summary| {
+            summary
+                .0
+                .into_iter()
+                .map(|(day, GMDDay { state, .. })| {
+                    state
+                        .into_iter()
+                        .map(|(product, quantity)| {
+                            let converted = units.convert(&product, quantity);
+                            (product, converted)
+                        })
+                        .collect::<BTreeMap<_, _>>()
+                        .pipe(|state| (day, state))
+                })
+                .collect::<BTreeMap<_, _>>()
+        })
+        .and_then(|days| match format.unwrap_or(ExportFormat::Json) {
+            ExportFormat::Json => serde_json::to_string_pretty(&days).wrap_err("serializing json"),
+            ExportFormat::Yaml => serde_yaml::to_string(&days).wrap_err("serializing yaml"),
+        })
+        .map(|exported| println!("{exported}"))
+}
+
+fn totals(workspace: &Workspace, units: &Units, options: SummaryOptions) -> Result<()> {
+    workspace
+        .summary(options)
+        .and_then(|summary| totals_rows(&summary, units))
+        .map(|rows| println!("{}", table(rows)))
+}
+
+/// days without anything eaten (e.g. only holding definitions) don't count towards the average
+fn totals_rows(summary: &GMDSummary, units: &Units) -> Result<Vec<Vec<String>>> {
+    let days_eaten = summary
+        .0
+        .values()
+        .filter(|day| !day.state.is_empty())
+        .count()
+        .pipe(rust_decimal::Decimal::from);
+    tracked_products(summary, units)
+        .into_iter()
+        .map(|product| {
+            summary
+                .0
+                .values()
+                .filter_map(|day| day.state.get(product).copied())
+                .try_fold(None::<Quantity>, |total, quantity| match total {
+                    Some(mut total) => total.try_add(quantity).map(|_| Some(total)),
+                    None => Ok(Some(quantity)),
+                })
+                .with_context(|| format!("summing up [{product}]"))
+                .map(|total| (product, total))
+        })
+        .collect::<Result<Vec<_>>>()
+        .map(|totals| {
+            once(
+                ["product", "total", "daily average"]
+                    .map(String::from)
+                    .to_vec(),
+            )
+            .chain(totals.into_iter().filter_map(|(product, total)| {
+                total.map(|total| {
+                    vec![
+                        product.to_string(),
+                        units.show(product, total).to_string(),
+                        Quantity {
+                            amount: total.amount / days_eaten,
+                            ..total
+                        }
+                        .pipe(|average| units.show(product, average))
+                        .to_string(),
+                    ]
+                })
+            }))
+            .collect_vec()
+        })
 }
 
 fn setup_logging() {
@@ -35,76 +874,110 @@ fn setup_logging() {
     }
 }
 
+/// the config found from the working directory, if any
+fn config() -> Result<Config> {
+    std::env::current_dir()
+        .wrap_err("finding working directory")
+        .and_then(|directory| Config::discover(&directory))
+        .map(Option::unwrap_or_default)
+}
+
 fn main() -> Result<()> {
     setup_logging();
     color_eyre::install().ok();
-    Cli::parse().pipe(|Cli { files }| {
-        files
-            .into_iter()
-            .map(|path| {
-                std::fs::read_to_string(&path)
-                    .wrap_err("reading file")
-                    .and_then(|contents| {
-                        GMDLog::from_gmd(&contents)
-                            .wrap_err("parsing file")
-                            .with_context(|| format!("reading '{}'", path.display()))
-                    })
-            })
-            .collect::<Result<Vec<_>>>()
-            .context("file(s) corrupted")
-            .and_then(|input| {
-                input
-                    .into_iter()
-                    .sorted_by_key(|log| {
-                        log.0.iter().find_map(|entry| match entry {
-                            LogEntry::StartDay(StartDay(day)) => Some(*day),
-                            _ => None,
-                        })
-                    })
-                    .flat_map(|GMDLog(entries)| entries.into_iter())
-                    .collect::<Vec<_>>()
-                    .pipe(GMDLog)
-                    .pipe_ref(GMDSummary::from_log)
-                    .map(|summary| {
-                        summary
-                            .0
-                            .values()
-                            .flat_map(|day| day.state.iter())
-                            .sorted_unstable_by_key(|(_, quantity)| quantity.amount)
-                            .rev()
-                            .map(|(name, _)| name)
-                            .unique_by(|name| *name)
-                            .collect_vec()
-                            .pipe(|tracked_products| {
-                                once(
-                                    once("day".to_string())
-                                        .chain(tracked_products.iter().map(|name| name.to_string()))
-                                        .collect_vec(),
-                                )
-                                .chain(
-                                    summary
-                                        .0
-                                        .iter()
-                                        .map(|(day, GMDDay { state, .. })| {
-                                            once(day.to_string())
-                                                .chain(tracked_products.iter().map(|product| {
-                                                    state
-                                                        .get(product)
-                                                        .copied()
-                                                        .map(|v| v.to_string())
-                                                        .unwrap_or_else(|| "~".into())
-                                                }))
-                                                .collect_vec()
-                                        })
-                                        .collect_vec(),
-                                )
-                            })
-                            .pipe(tabled::tables::IterTable::new)
-                            .to_string()
-                    })
-                    .map(|table| {
-                        println!("{table}");
-                    })
-            })
+    let config = config();
+    let args = std::env::args_os().collect();
+    Cli::parse_from(match &config {
+        Ok(config) => config.expand_alias(args),
+        Err(_) => args,
     })
+    .pipe(
+        |Cli {
+             command,
+             input,
+             units,
+         }| {
+            let command = match command.unwrap_or(Commands::Summary { input, units }) {
+                // formatting doesn't read the config, so a broken one doesn't stop it
+                command @ Commands::Fmt { .. } => command,
+                command => config.and_then(|config| command.configure(&config))?,
+            };
+            match command {
+                Commands::Summary { input, units } => input
+                    .load()
+                    .and_then(|workspace| summary(&workspace, &units, input.options())),
+                Commands::Meals { input, units } => input
+                    .load()
+                    .and_then(|workspace| meals(&workspace, &units, input.options())),
+                Commands::Timeline { input, units } => input
+                    .load()
+                    .and_then(|workspace| timeline(&workspace, &units, input.options())),
+                Commands::Targets { input, units } => input
+                    .load()
+                    .and_then(|workspace| targets(&workspace, &units, input.options())),
+                Commands::Check { input } => check(&input),
+                Commands::Fmt { check, files } => fmt(files, check),
+                Commands::Products { input } => {
+                    input.load().and_then(|workspace| products(&workspace))
+                }
+                Commands::Versions {
+                    product,
+                    threshold,
+                    input,
+                } => input.load().and_then(|workspace| {
+                    versions(
+                        &workspace,
+                        product.map(ProductName::new),
+                        threshold,
+                        input.options(),
+                    )
+                }),
+                Commands::Export {
+                    format,
+                    input,
+                    units,
+                } => input
+                    .load()
+                    .and_then(|workspace| export(&workspace, format, &units, input.options())),
+                Commands::Totals { input, units } => input
+                    .load()
+                    .and_then(|workspace| totals(&workspace, &units, input.options())),
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{totals_rows, Units};
+    use eyre::Result;
+    use gmd_parser::{calculator::GMDSummary, models::GMDLog, parser::FromGMD};
+
+    #[test]
+    fn test_totals_average_is_rounded_once() -> Result<()> {
+        const LOG: &str = "nutrient Niacyna
+
+2024-01-20
+eat 0.003g of Niacyna
+
+2024-01-21
+eat 0.0012g of Niacyna
+";
+
+        let log = GMDLog::from_gmd(LOG)?;
+        let summary = GMDSummary::from_log(&log)?;
+        let units = Units {
+            energy: None,
+            nutrients: Default::default(),
+            columns: vec![],
+        };
+        assert_eq!(
+            totals_rows(&summary, &units)?,
+            vec![
+                vec!["product", "total", "daily average"],
+                vec!["Niacyna", "0.0042g", "0.0021g"],
+            ]
+        );
+        Ok(())
+    }
 }
@@ -0,0 +1,250 @@
+use crate::{
+    error::Res,
+    models::{Gram, ProductName, Quantity, Target, UnitOfMeasure},
+    parser::{FromGMD, SpecialUnitOfMeasure, ToGMD},
+    workspace::Source,
+};
+use eyre::{Result, WrapErr};
+use itertools::Itertools;
+use nom::{branch::alt, Parser};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+use tap::prelude::*;
+
+pub const FILE_NAME: &str = "gmd.toml";
+
+/// unit a nutrient is shown in - any unit it can be logged in, or milligrams and micrograms
+#[derive(Debug, Clone, Copy, PartialEq, Eq, derive_more::From)]
+pub enum DisplayUnit {
+    Logged(UnitOfMeasure),
+    Special(SpecialUnitOfMeasure),
+}
+
+impl DisplayUnit {
+    /// amount of `quantity` in this unit, `None` if it can't be converted to it
+    pub fn convert(&self, quantity: Quantity) -> Option<Decimal> {
+        match self {
+            DisplayUnit::Logged(unit) => {
+                quantity.convert_to(*unit).map(|converted| converted.amount)
+            }
+            DisplayUnit::Special(unit) => quantity
+                .convert_to(Gram.into())
+                .map(|grams| unit.convert_grams(grams.amount)),
+        }
+    }
+}
+
+impl std::fmt::Display for DisplayUnit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DisplayUnit::Logged(unit) => write!(f, "{unit}"),
+            DisplayUnit::Special(unit) => f.write_str(&unit.to_gmd()),
+        }
+    }
+}
+
+impl FromGMD for DisplayUnit {
+    fn parse(input: &str) -> Res<'_, Self> {
+        alt((
+            SpecialUnitOfMeasure::parse.map(Self::from),
+            UnitOfMeasure::parse.map(Self::from),
+        ))
+        .parse(input)
+    }
+}
+
+/// defaults for the command line, command line flags take precedence
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// where it was read from
+    #[serde(skip)]
+    pub path: PathBuf,
+    /// read when no inputs are given, relative to the config file
+    pub inputs: Vec<PathBuf>,
+    pub strict: bool,
+    /// `Energia = "kJ"` - unit a nutrient is shown in
+    pub units: BTreeMap<String, String>,
+    /// nutrients shown first, in this order
+    pub columns: Vec<String>,
+    /// `Białko = "150g..200g"` - in effect on every day which doesn't set its own
+    pub targets: BTreeMap<String, String>,
+    /// `week = ["summary", "--energy", "kj"]` - replaces the first argument when it matches
+    pub aliases: BTreeMap<String, Vec<String>>,
+    /// default format of `export`
+    pub format: Option<String>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        std::fs::read_to_string(path)
+            .wrap_err("reading file")
+            .and_then(|contents| toml::from_str::<Self>(&contents).wrap_err("parsing file"))
+            .map(|config| {
+                let directory = path.parent().unwrap_or_else(|| Path::new(""));
+                Self {
+                    path: path.to_path_buf(),
+                    inputs: config
+                        .inputs
+                        .iter()
+                        .map(|input| directory.join(input))
+                        .collect(),
+                    ..config
+                }
+            })
+            .with_context(|| format!("loading config '{}'", path.display()))
+    }
+
+    /// places a config is looked for in - `start` and the directories above it, up to the root of
+    /// the repository it's in, followed by the user's config directory
+    pub fn candidates(start: &Path) -> Vec<PathBuf> {
+        let user = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .map(|directory| directory.join("gmd").join(FILE_NAME));
+        start
+            .ancestors()
+            .take_while_inclusive(|directory| !directory.join(".git").exists())
+            .map(|directory| directory.join(FILE_NAME))
+            .chain(user)
+            .collect()
+    }
+
+    /// the first config found, see [`Config::candidates`]
+    pub fn discover(start: &Path) -> Result<Option<Self>> {
+        Self::candidates(start)
+            .into_iter()
+            .find(|candidate| candidate.is_file())
+            .map(|path| Self::load(&path))
+            .transpose()
+    }
+
+    pub fn units(&self) -> Result<BTreeMap<ProductName, DisplayUnit>> {
+        self.units
+            .iter()
+            .map(|(nutrient, unit)| {
+                DisplayUnit::from_gmd(unit)
+                    .with_context(|| format!("unit of [{nutrient}]"))
+                    .map(|unit| (ProductName::new(nutrient), unit))
+            })
+            .collect()
+    }
+
+    pub fn columns(&self) -> Vec<ProductName> {
+        self.columns.iter().map(ProductName::new).collect()
+    }
+
+    pub fn targets(&self) -> Result<Vec<Target>> {
+        self.targets
+            .iter()
+            .map(|(nutrient, range)| {
+                Target::from_gmd(&format!("target {range} of {nutrient}"))
+                    .with_context(|| format!("target for [{nutrient}]"))
+            })
+            .collect()
+    }
+
+    /// the targets as a log preceding all the others, so that they apply to every day
+    pub fn source(&self) -> Result<Option<Source>> {
+        self.targets().map(|targets| match targets.is_empty() {
+            true => None,
+            false => Source::Memory {
+                name: self.path.clone(),
+                contents: targets
+                    .iter()
+                    .map(|target| format!("{}\n", target.to_gmd()))
+                    .join(""),
+            }
+            .pipe(Some),
+        })
+    }
+
+    /// replaces the first argument after the program name with its alias
+    pub fn expand_alias(&self, args: Vec<OsString>) -> Vec<OsString> {
+        match args
+            .get(1)
+            .and_then(|first| first.to_str())
+            .and_then(|first| self.aliases.get(first))
+        {
+            Some(alias) => args[..1]
+                .iter()
+                .cloned()
+                .chain(alias.iter().map(OsString::from))
+                .chain(args[2..].iter().cloned())
+                .collect(),
+            None => args,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, DisplayUnit};
+    use crate::{
+        models::{Kilojoule, ProductName, Quantity, UnitOfMeasure},
+        parser::{FromGMD, ToGMD},
+    };
+    use eyre::Result;
+    use rust_decimal_macros::dec;
+    use std::ffi::OsString;
+
+    const CONFIG: &str = r#"
+inputs = ["diary/"]
+columns = ["Energia", "Białko"]
+format = "yaml"
+
+[units]
+Energia = "kJ"
+"Witamina C" = "mg"
+
+[targets]
+"Białko" = "150g..200g"
+
+[aliases]
+week = ["summary", "--energy", "kcal"]
+"#;
+
+    #[test]
+    fn test_config() -> Result<()> {
+        let config = toml::from_str::<Config>(CONFIG)?;
+        assert_eq!(
+            config.units()?.get(&ProductName::new("Energia")),
+            Some(&DisplayUnit::from(UnitOfMeasure::from(Kilojoule)))
+        );
+        assert_eq!(
+            config
+                .units()?
+                .get(&ProductName::new("Witamina C"))
+                .and_then(|unit| unit.convert(Quantity::from_gmd("0.0059g").ok()?)),
+            Some(dec!(5.9))
+        );
+        assert_eq!(
+            config
+                .targets()?
+                .iter()
+                .map(|target| target.to_gmd())
+                .collect::<Vec<_>>(),
+            vec!["target 150g..200g of Białko"]
+        );
+        let args = |args: &[&str]| args.iter().map(OsString::from).collect::<Vec<_>>();
+        assert_eq!(
+            config.expand_alias(args(&["gmd", "week", "january.gmd"])),
+            args(&["gmd", "summary", "--energy", "kcal", "january.gmd"])
+        );
+        assert_eq!(
+            config.expand_alias(args(&["gmd", "check"])),
+            args(&["gmd", "check"])
+        );
+        assert!(toml::from_str::<Config>("colums = []").is_err());
+        let error = toml::from_str::<Config>("[targets]\n\"Białko\" = \"-5g\"")?
+            .targets()
+            .expect_err("targets have to be above zero");
+        assert!(format!("{error:?}").contains("target has to be above zero"));
+        Ok(())
+    }
+}
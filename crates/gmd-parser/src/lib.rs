@@ -1,10 +1,201 @@
+pub mod config;
 pub mod models;
 pub mod parser;
+pub mod workspace;
 pub mod error {
-    use nom::IResult;
-    use nom_supreme::error::ErrorTree;
+    use crate::models::Span;
+    use itertools::Itertools;
+    use nom::{IResult, Offset};
+    use nom_supreme::error::{BaseErrorKind, ErrorTree, StackContext};
+    use tap::prelude::*;
 
     pub type Res<'a, T> = IResult<&'a str, T, ErrorTree<&'a str>>;
+
+    /// errors made of several problems found in the log, which only know the [`FileId`] of
+    /// their spans until a workspace names the files
+    ///
+    /// [`FileId`]: crate::models::FileId
+    pub trait Located {
+        /// the first line, such as `3 product(s) could not be flattened`
+        fn heading(&self) -> String;
+
+        /// every problem along with the spans it was found at
+        fn problems(&self) -> Vec<(String, Vec<&Span>)>;
+
+        /// a line for every problem following the heading, led by its spans written by
+        /// `location`. [`std::fmt::Display`] writes them as they are
+        fn located(&self, location: impl Fn(&Span) -> String) -> String {
+            self.problems()
+                .into_iter()
+                .map(|(problem, spans)| match spans.is_empty() {
+                    true => format!("\n{problem}"),
+                    false => format!(
+                        "\n{}: {problem}",
+                        spans.into_iter().map(&location).join(", ")
+                    ),
+                })
+                .join("")
+                .pipe(|problems| format!("{}{problems}", self.heading()))
+        }
+    }
+
+    /// parse failure mapped back onto the source it happened in
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct ParseDiagnostic {
+        /// byte offset of the failure
+        pub offset: usize,
+        /// 1-based
+        pub line: usize,
+        /// 1-based, counted in characters
+        pub column: usize,
+        /// everything that would have been accepted at the failing position
+        pub expected: Vec<String>,
+        /// constructs that were being parsed, outermost first
+        pub constructs: Vec<&'static str>,
+        pub source_line: String,
+        /// number of underlined characters
+        pub length: usize,
+    }
+
+    struct Leaf<'input> {
+        location: &'input str,
+        expected: String,
+        contexts: Vec<&'static str>,
+    }
+
+    fn leaves<'input>(
+        tree: &ErrorTree<&'input str>,
+        contexts: &[&'static str],
+    ) -> Vec<Leaf<'input>> {
+        match tree {
+            ErrorTree::Base { location, kind } => Leaf {
+                location,
+                expected: match kind {
+                    BaseErrorKind::Expected(expectation) => expectation.to_string(),
+                    BaseErrorKind::Kind(kind) => contexts
+                        .last()
+                        .map(|context| context.to_string())
+                        .unwrap_or_else(|| kind.description().to_string()),
+                    BaseErrorKind::External(error) => contexts
+                        .last()
+                        .map(|context| format!("valid {context} ({error})"))
+                        .unwrap_or_else(|| error.to_string()),
+                },
+                contexts: contexts.to_vec(),
+            }
+            .pipe(|leaf| vec![leaf]),
+            ErrorTree::Stack {
+                base,
+                contexts: stack,
+            } => contexts
+                .iter()
+                .copied()
+                .chain(stack.iter().rev().filter_map(|(_, context)| match context {
+                    StackContext::Context(context) => Some(*context),
+                    StackContext::Kind(_) => None,
+                }))
+                .collect_vec()
+                .pipe(|contexts| leaves(base, &contexts)),
+            ErrorTree::Alt(alternatives) => alternatives
+                .iter()
+                .flat_map(|alternative| leaves(alternative, contexts))
+                .collect_vec(),
+        }
+    }
+
+    impl ParseDiagnostic {
+        /// reports the alternatives that made it furthest into `source`,
+        /// `tree` must come from parsing (a slice of) `source`
+        pub fn new(source: &str, tree: &ErrorTree<&str>) -> Self {
+            let leaves = leaves(tree, &[]);
+            let offset = leaves
+                .iter()
+                .map(|leaf| source.offset(leaf.location))
+                .max()
+                .unwrap_or_default();
+            let furthest = leaves
+                .iter()
+                .filter(|leaf| source.offset(leaf.location) == offset)
+                .collect_vec();
+            let (before, after) = source.split_at(offset);
+            let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or_default();
+            Self {
+                offset,
+                line: before.matches('\n').count() + 1,
+                column: before[line_start..].chars().count() + 1,
+                expected: furthest
+                    .iter()
+                    .map(|leaf| leaf.expected.clone())
+                    .unique()
+                    .collect_vec(),
+                // constructs all the alternatives agree on, type names (as opposed to keywords)
+                // are the only capitalized contexts
+                constructs: furthest
+                    .iter()
+                    .map(|leaf| leaf.contexts.as_slice())
+                    .reduce(|common, contexts| {
+                        common
+                            .iter()
+                            .zip(contexts)
+                            .take_while(|(left, right)| left == right)
+                            .count()
+                            .pipe(|shared| &common[..shared])
+                    })
+                    .unwrap_or_default()
+                    .iter()
+                    .copied()
+                    .filter(|context| context.starts_with(char::is_uppercase))
+                    .dedup()
+                    .collect_vec(),
+                source_line: source[line_start..]
+                    .lines()
+                    .next()
+                    .unwrap_or_default()
+                    .to_string(),
+                length: after
+                    .chars()
+                    .take_while(|c| !c.is_whitespace())
+                    .count()
+                    .max(1),
+            }
+        }
+    }
+
+    impl std::fmt::Display for ParseDiagnostic {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let gutter = " ".repeat(self.line.to_string().len());
+            let padding = self
+                .source_line
+                .chars()
+                .take(self.column - 1)
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect::<String>();
+            match self.expected.as_slice() {
+                [expected] => write!(f, "expected {expected}")?,
+                expected => write!(f, "expected one of {}", expected.iter().join(", "))?,
+            }
+            if !self.constructs.is_empty() {
+                write!(f, " while parsing {}", self.constructs.iter().join(" > "))?;
+            }
+            writeln!(f, " at line {}, column {}", self.line, self.column)?;
+            writeln!(f, "{gutter} |")?;
+            writeln!(f, "{} | {}", self.line, self.source_line)?;
+            write!(f, "{gutter} | {padding}{}", "^".repeat(self.length))
+        }
+    }
+
+    impl std::error::Error for ParseDiagnostic {}
+
+    impl ParseDiagnostic {
+        pub fn report(source: &str, error: nom::Err<ErrorTree<&str>>) -> eyre::Report {
+            match error {
+                nom::Err::Error(e) | nom::Err::Failure(e) => {
+                    Self::new(source, &e).pipe(eyre::Report::new)
+                }
+                nom::Err::Incomplete(_) => eyre::eyre!("incomplete input"),
+            }
+        }
+    }
 }
 
 use itertools::Itertools;
@@ -16,8 +207,9 @@ use tap::prelude::*;
 macro_rules! type_name {
     () => {
         std::any::type_name::<Self>()
-            .split("::")
-            .last()
+            .split('<')
+            .next()
+            .and_then(|path| path.split("::").last())
             .unwrap_or(std::any::type_name::<Self>())
     };
 }
@@ -55,8 +247,11 @@ impl<T> NonEmpty<T> {
 
 pub mod calculator {
     use crate::{
+        error::Located,
         models::{
-            AmountOf, Eat, GMDLog, LogEntry, ProductDefinition, ProductName, Quantity, StartDay,
+            AmountOf, Eat, GMDLog, LogEntry, Meal, Nutrient, Portion, ProductDefinition,
+            ProductName, Quantity, Span, Spanned, SpannedGMDLog, StartDay, Target, Uneat,
+            UnitOfMeasure,
         },
         NonEmptyExt,
     };
@@ -64,18 +259,124 @@ pub mod calculator {
     use itertools::Itertools;
     use nonempty::NonEmpty;
     use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
     use std::{
-        collections::BTreeMap,
+        collections::{BTreeMap, BTreeSet},
         iter::once,
-        ops::{Div, Mul, MulAssign},
+        ops::{Mul, MulAssign},
     };
     use tap::prelude::*;
-    use tracing::{info, info_span, warn};
+    use tracing::{debug, info, info_span, warn};
+
+    #[derive(Debug)]
+    pub struct GMDMeal<'input> {
+        pub meal: &'input Meal,
+        /// what was eaten during this meal, also included in [`GMDDay::state`]
+        pub state: BTreeMap<ProductName, Quantity>,
+    }
 
     #[derive(Default, Debug)]
     pub struct GMDDay<'input> {
         pub state: BTreeMap<ProductName, Quantity>,
         pub defined_products: BTreeMap<&'input ProductName, &'input ProductDefinition>,
+        /// every definition logged on this day in order, [`GMDDay::defined_products`] only keeps
+        /// the latest one of each product
+        pub definitions: Vec<(Option<&'input Span>, &'input ProductDefinition)>,
+        /// in the order they were first logged, meals with the same name are merged
+        pub meals: Vec<GMDMeal<'input>>,
+        /// in the order they were logged
+        pub eaten: Vec<GMDEaten<'input>>,
+        /// only the nutrients declared on this day, see [`GMDSummary::nutrients`]
+        pub nutrients: BTreeSet<&'input ProductName>,
+        /// only the targets set on this day, see [`GMDSummary::targets`]
+        pub targets: BTreeMap<&'input ProductName, &'input Target>,
+    }
+
+    /// what a single eat (or uneat) entry added to the day
+    #[derive(Debug)]
+    pub struct GMDEaten<'input> {
+        pub entry: &'input LogEntry,
+        /// of the entry itself, or of the meal it's logged under
+        pub time: Option<chrono::NaiveTime>,
+        pub state: BTreeMap<ProductName, Quantity>,
+        /// definitions the eaten product was broken down through, its own included
+        pub definitions: Vec<&'input ProductDefinition>,
+    }
+
+    impl GMDEaten<'_> {
+        pub fn product(&self) -> Option<&ProductName> {
+            match self.entry {
+                LogEntry::Eat(Eat { product, .. })
+                | LogEntry::Uneat(Uneat(Eat { product, .. })) => Some(product),
+                _ => None,
+            }
+        }
+    }
+
+    impl<'input> GMDDay<'input> {
+        fn meal(&mut self, meal: &'input Meal) -> &mut GMDMeal<'input> {
+            if !self
+                .meals
+                .iter()
+                .any(|existing| existing.meal.name == meal.name)
+            {
+                self.meals.push(GMDMeal {
+                    meal,
+                    state: Default::default(),
+                });
+            }
+            self.meals
+                .iter_mut()
+                .find(|existing| existing.meal.name == meal.name)
+                .expect("meal was just added")
+        }
+
+        /// eaten entries ordered by time, each with the day's running total after it;
+        /// entries without a time are assumed to happen at the time of the entry before them
+        pub fn timeline(
+            &self,
+        ) -> Result<Vec<(&GMDEaten<'input>, BTreeMap<ProductName, Quantity>)>> {
+            self.eaten
+                .iter()
+                .scan(None, |last_time, eaten| {
+                    *last_time = eaten.time.or(*last_time);
+                    Some((*last_time, eaten))
+                })
+                .sorted_by_key(|(time, _)| *time)
+                .scan(BTreeMap::new(), |total, (_, eaten)| {
+                    eaten
+                        .state
+                        .iter()
+                        .try_for_each(|(product, quantity)| {
+                            add_eaten(total, quantity.of(product.clone()))
+                        })
+                        .map(|_| (eaten, total.clone()))
+                        .pipe(Some)
+                })
+                .collect()
+        }
+    }
+
+    fn add_eaten(
+        state: &mut BTreeMap<ProductName, Quantity>,
+        AmountOf {
+            quantity,
+            inner: product_name,
+        }: AmountOf<ProductName>,
+    ) -> Result<()> {
+        state
+            .entry(product_name)
+            .or_insert_with(|| Quantity {
+                amount: Default::default(),
+                unit: quantity.unit,
+            })
+            .pipe(|total| {
+                total.try_add(quantity).tap_ok(|_| {
+                    if total.amount.is_sign_negative() {
+                        warn!(%total, "negative total");
+                    }
+                })
+            })
     }
 
     fn boxed<'a, T>(iter: impl Iterator<Item = T> + 'a) -> Box<dyn Iterator<Item = T> + 'a> {
@@ -83,7 +384,75 @@ pub mod calculator {
     }
 
     #[derive(Default, Debug)]
-    pub struct GMDSummary<'input>(pub BTreeMap<chrono::NaiveDate, GMDDay<'input>>);
+    pub struct GMDSummary<'input>(
+        pub BTreeMap<chrono::NaiveDate, GMDDay<'input>>,
+        /// products, nutrients and targets logged before the first day, in effect on all of them
+        pub GMDDay<'input>,
+    );
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum TargetStatus {
+        Met,
+        /// how much is missing to reach the target
+        Deficit(Quantity),
+        /// how much more than the target was eaten
+        Surplus(Quantity),
+    }
+
+    #[derive(Debug)]
+    pub struct TargetProgress<'input> {
+        pub target: &'input Target,
+        /// expressed in the unit of the target
+        pub eaten: Quantity,
+        /// of the lower bound of the target
+        pub percent_met: Decimal,
+        pub status: TargetStatus,
+    }
+
+    impl<'input> TargetProgress<'input> {
+        pub fn new(target: &'input Target, eaten: Option<Quantity>) -> Result<Self> {
+            let difference = |bound: Quantity, eaten: Quantity| Quantity {
+                amount: (bound.amount - eaten.amount).abs(),
+                unit: bound.unit,
+            };
+            eaten
+                .unwrap_or(Quantity {
+                    amount: Decimal::ZERO,
+                    unit: target.lower.unit,
+                })
+                .pipe(|eaten| {
+                    eaten.convert_to(target.lower.unit).with_context(|| {
+                        format!(
+                            "[{eaten}] cannot be compared with target [{}]",
+                            target.lower
+                        )
+                    })
+                })
+                .map(|eaten| {
+                    let upper = target
+                        .upper
+                        .and_then(|upper| upper.convert_to(target.lower.unit))
+                        .unwrap_or(target.lower);
+                    Self {
+                        target,
+                        eaten,
+                        percent_met: match target.lower.amount.is_zero() {
+                            true => Decimal::ONE_HUNDRED,
+                            false => eaten.amount / target.lower.amount * Decimal::ONE_HUNDRED,
+                        },
+                        status: match eaten.amount {
+                            amount if amount < target.lower.amount => {
+                                TargetStatus::Deficit(difference(target.lower, eaten))
+                            }
+                            amount if amount > upper.amount => {
+                                TargetStatus::Surplus(difference(upper, eaten))
+                            }
+                            _ => TargetStatus::Met,
+                        },
+                    }
+                })
+        }
+    }
 
     #[derive(Debug, Clone, Copy)]
     pub struct Ratio(Decimal);
@@ -117,19 +486,402 @@ pub mod calculator {
     }
 
     impl Quantity {
+        /// fails for quantities measuring different things and for zero `other`
         pub fn ratio(self, other: Quantity) -> Result<Ratio> {
-            match (self.unit, other.unit) {
-                (left, right) if left == right => left.pipe(Some),
-                _other => None,
+            self.convert_to(other.unit)
+                .and_then(|this| this.amount.checked_div(other.amount))
+                .map(Ratio)
+                .with_context(|| format!("cannot calculate ratio of {self:?} within {other:?}"))
+        }
+    }
+
+    impl ProductDefinition {
+        /// servings are looked up by name and multiplied by their count
+        pub fn quantity_of(&self, portion: &Portion) -> Result<Quantity> {
+            match portion {
+                Portion::Quantity(quantity) => Ok(*quantity),
+                Portion::Servings { count, serving } => self
+                    .serving(serving)
+                    .with_context(|| format!("product [{}] has no serving [{serving}]", self.name))
+                    .map(|serving| Quantity {
+                        amount: serving.quantity.amount * count,
+                        unit: serving.quantity.unit,
+                    }),
+            }
+        }
+    }
+
+    impl Quantity {
+        const HUNDRED: Decimal = dec!(100);
+
+        /// percentages are resolved against `whole`, absolute quantities are left as they are
+        pub fn share_of(self, whole: Quantity) -> Quantity {
+            match self.unit {
+                UnitOfMeasure::Percent(_) => Quantity {
+                    amount: whole.amount * self.amount / Self::HUNDRED,
+                    unit: whole.unit,
+                },
+                _ => self,
+            }
+        }
+    }
+
+    /// products whose definitions contain each other, the first one is repeated at the end
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct DefinitionCycle(pub Vec<ProductName>);
+
+    impl std::fmt::Display for DefinitionCycle {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "definition cycle: {}", self.0.iter().join(" -> "))
+        }
+    }
+
+    impl std::error::Error for DefinitionCycle {}
+
+    impl DefinitionCycle {
+        /// the first cycle found going through `start`, given the definitions in effect.
+        /// products are only visited once, a product which didn't lead back to `start` the first
+        /// time won't the second time either
+        fn through<'a>(
+            start: &'a ProductName,
+            definitions: &BTreeMap<&'a ProductName, &'a ProductDefinition>,
+        ) -> Option<Self> {
+            fn walk<'a>(
+                start: &'a ProductName,
+                definitions: &BTreeMap<&'a ProductName, &'a ProductDefinition>,
+                path: &mut Vec<&'a ProductName>,
+                visited: &mut BTreeSet<&'a ProductName>,
+            ) -> Option<DefinitionCycle> {
+                definitions
+                    .get(path[path.len() - 1])
+                    .and_then(|definition| definition.ingredients.inner.as_ref())
+                    .into_iter()
+                    .flatten()
+                    .map(|ingredient| &ingredient.inner)
+                    .find_map(|next| match next == start {
+                        true => path
+                            .iter()
+                            .copied()
+                            .chain(once(start))
+                            .cloned()
+                            .collect_vec()
+                            .pipe(DefinitionCycle)
+                            .pipe(Some),
+                        false if visited.insert(next) => {
+                            path.push(next);
+                            let found = walk(start, definitions, path, visited);
+                            path.pop();
+                            found
+                        }
+                        false => None,
+                    })
+            }
+            walk(
+                start,
+                definitions,
+                &mut vec![start],
+                &mut BTreeSet::from([start]),
+            )
+        }
+
+        /// the same for every rotation of the cycle
+        fn key(&self) -> Vec<&ProductName> {
+            (&self.0[..self.0.len() - 1]).pipe(|products| {
+                products
+                    .iter()
+                    .position_min()
+                    .map(|smallest| {
+                        products[smallest..]
+                            .iter()
+                            .chain(&products[..smallest])
+                            .collect_vec()
+                    })
+                    .unwrap_or_default()
+            })
+        }
+    }
+
+    /// every cycle is reported once, next to the definition which closed it
+    fn definition_cycles<'a, Source: Clone>(
+        entries: impl Iterator<Item = (Source, &'a LogEntry)>,
+    ) -> Vec<(Source, DefinitionCycle)> {
+        entries
+            .fold(
+                (BTreeMap::new(), Vec::<(Source, DefinitionCycle)>::new()),
+                |(mut definitions, mut found), (source, entry)| {
+                    if let LogEntry::Define(product) = entry {
+                        definitions.insert(&product.name, product);
+                        DefinitionCycle::through(&product.name, &definitions)
+                            .into_iter()
+                            .filter(|cycle| {
+                                found
+                                    .iter()
+                                    .all(|(_, existing)| existing.key() != cycle.key())
+                            })
+                            .collect_vec()
+                            .into_iter()
+                            .for_each(|cycle| found.push((source.clone(), cycle)));
+                    }
+                    (definitions, found)
+                },
+            )
+            .1
+    }
+
+    impl GMDLog {
+        pub fn definition_cycles(&self) -> Vec<DefinitionCycle> {
+            self.0
+                .iter()
+                .map(|entry| ((), entry))
+                .pipe(definition_cycles)
+                .into_iter()
+                .map(|(_, cycle)| cycle)
+                .collect()
+        }
+    }
+
+    impl SpannedGMDLog {
+        pub fn definition_cycles(&self) -> Vec<Spanned<DefinitionCycle>> {
+            self.0
+                .iter()
+                .map(|Spanned { span, inner }| (span, inner))
+                .pipe(definition_cycles)
+                .into_iter()
+                .map(|(span, inner)| Spanned {
+                    span: span.clone(),
+                    inner,
+                })
+                .collect()
+        }
+    }
+
+    /// what a product name refers to
+    #[derive(Debug, Clone, Copy)]
+    pub enum Definition<'input> {
+        Nutrient(&'input ProductName),
+        Product(&'input ProductDefinition),
+    }
+
+    impl<'input> Definition<'input> {
+        pub fn name(&self) -> &'input ProductName {
+            match self {
+                Definition::Nutrient(name) => name,
+                Definition::Product(product) => &product.name,
+            }
+        }
+
+        /// nutrients can only be measured directly, they have no servings
+        pub fn quantity_of(&self, portion: &Portion) -> Result<Quantity> {
+            match (self, portion) {
+                (Definition::Product(product), portion) => product.quantity_of(portion),
+                (Definition::Nutrient(_), Portion::Quantity(quantity)) => Ok(*quantity),
+                (Definition::Nutrient(name), Portion::Servings { serving, .. }) => {
+                    Err(eyre!("nutrient [{name}] has no serving [{serving}]"))
+                }
+            }
+        }
+    }
+
+    /// what happens to products which can't be broken down into their ingredients
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub enum StrictMode {
+        /// warn and count the product as if it was a nutrient
+        #[default]
+        Lenient,
+        /// collect every such product and fail once the whole log is read
+        Strict,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct SummaryOptions {
+        pub strict: StrictMode,
+        /// the day meals and eaten products logged before the first day are filed under.
+        /// without it they are rejected, so that the summary doesn't depend on when it's made
+        pub reference_date: Option<chrono::NaiveDate>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum FlattenError {
+        UnknownComposition {
+            product: ProductName,
+        },
+        UnknownIngredient {
+            product: ProductName,
+            ingredient: ProductName,
+        },
+        /// `quantity` can't be compared with the quantity the ingredients are declared `per`
+        IncompatibleQuantity {
+            product: ProductName,
+            quantity: Quantity,
+            per: Quantity,
+        },
+    }
+
+    impl FlattenError {
+        /// the product which couldn't be flattened
+        pub fn product(&self) -> &ProductName {
+            match self {
+                FlattenError::UnknownComposition { product }
+                | FlattenError::UnknownIngredient { product, .. }
+                | FlattenError::IncompatibleQuantity { product, .. } => product,
             }
-            .map(|_| self.amount.div(&other.amount).pipe(Ratio))
-            .with_context(|| format!("cannot calculate ratio of {self:?} within {other:?}"))
         }
     }
 
+    impl std::fmt::Display for FlattenError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                FlattenError::UnknownComposition { product } => {
+                    write!(f, "composition of [{product}] is not known")
+                }
+                FlattenError::UnknownIngredient {
+                    product,
+                    ingredient,
+                } => write!(f, "ingredient [{ingredient}] of [{product}] is not defined"),
+                FlattenError::IncompatibleQuantity {
+                    product,
+                    quantity,
+                    per,
+                } => write!(
+                    f,
+                    "[{quantity}] of [{product}] can't be compared with [{per}] it's defined for"
+                ),
+            }
+        }
+    }
+
+    impl std::error::Error for FlattenError {}
+
+    /// everything strict mode found, each with the definition it was found in if it's known
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct FlattenErrors(pub Vec<(Option<Span>, FlattenError)>);
+
+    impl Located for FlattenErrors {
+        fn heading(&self) -> String {
+            format!("{} product(s) could not be flattened", self.0.len())
+        }
+
+        fn problems(&self) -> Vec<(String, Vec<&Span>)> {
+            self.0
+                .iter()
+                .map(|(span, error)| (error.to_string(), span.iter().collect()))
+                .collect()
+        }
+    }
+
+    impl std::fmt::Display for FlattenErrors {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str(&self.located(Span::to_string))
+        }
+    }
+
+    impl std::error::Error for FlattenErrors {}
+
+    /// redefinitions changing any ingredient by more than this fraction are warned about
+    pub const REDEFINITION_THRESHOLD: Decimal = dec!(0.1);
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct IngredientChange {
+        pub product: ProductName,
+        /// `None` when the ingredient was added
+        pub before: Option<Quantity>,
+        /// `None` when the ingredient was removed
+        pub after: Option<Quantity>,
+    }
+
+    impl IngredientChange {
+        /// `None` for added and removed ingredients
+        pub fn relative(&self) -> Option<Decimal> {
+            self.before
+                .zip(self.after)
+                .filter(|(before, _)| !before.amount.is_zero())
+                .map(|(before, after)| (after.amount - before.amount) / before.amount)
+        }
+
+        pub fn exceeds(&self, threshold: Decimal) -> bool {
+            self.relative()
+                .map(|relative| relative.abs() > threshold)
+                .unwrap_or(true)
+        }
+    }
+
+    /// changed ingredients, both versions scaled to the declared quantity of the older one
+    #[derive(Debug, Clone, PartialEq, Eq, Default)]
+    pub struct DefinitionDiff(pub Vec<IngredientChange>);
+
+    impl DefinitionDiff {
+        pub fn exceeds(&self, threshold: Decimal) -> bool {
+            self.0.iter().any(|change| change.exceeds(threshold))
+        }
+    }
+
+    impl ProductDefinition {
+        fn ingredient_amounts(&self, per: Quantity) -> Result<BTreeMap<&ProductName, Quantity>> {
+            match self.ingredients.inner {
+                None => Ok(BTreeMap::new()),
+                Some(_) => per.of_ingredients(self).and_then(|ingredients| {
+                    ingredients.into_iter().try_fold(
+                        BTreeMap::new(),
+                        |mut amounts, AmountOf { quantity, inner }| {
+                            match amounts.get_mut(inner) {
+                                Some(total) => Quantity::try_add(total, quantity),
+                                None => {
+                                    amounts.insert(inner, quantity);
+                                    Ok(())
+                                }
+                            }
+                            .map(|_| amounts)
+                        },
+                    )
+                }),
+            }
+        }
+
+        pub fn diff(&self, newer: &ProductDefinition) -> Result<DefinitionDiff> {
+            self.ingredient_amounts(self.ingredients.quantity)
+                .and_then(|before| {
+                    newer
+                        .ingredient_amounts(self.ingredients.quantity)
+                        .map(|after| (before, after))
+                })
+                .map(|(before, after)| {
+                    before
+                        .keys()
+                        .chain(after.keys())
+                        .unique()
+                        .map(|product| IngredientChange {
+                            product: (*product).clone(),
+                            before: before.get(product).copied(),
+                            after: after.get(product).copied(),
+                        })
+                        .filter(|change| change.before != change.after)
+                        .collect_vec()
+                        .pipe(DefinitionDiff)
+                })
+                .with_context(|| format!("comparing versions of [{}]", self.name))
+        }
+    }
+
+    #[derive(Debug)]
+    pub struct ProductVersion<'input> {
+        pub definition: &'input ProductDefinition,
+        /// where it was defined, if the log it comes from is spanned
+        pub span: Option<&'input Span>,
+        /// `None` for the library definition, in effect until the product is defined on a day
+        pub effective_from: Option<chrono::NaiveDate>,
+        /// the day the next version takes over
+        pub effective_until: Option<chrono::NaiveDate>,
+        /// days on which this version was eaten, by itself or as an ingredient of another product
+        pub used_on: Vec<chrono::NaiveDate>,
+    }
+
     struct GMDSummaryBuilder<'input> {
         current: GMDSummary<'input>,
-        current_day: chrono::NaiveDate,
+        /// `None` until the first day starts, see [`GMDSummary::library`]
+        current_day: Option<chrono::NaiveDate>,
+        current_meal: Option<&'input Meal>,
+        options: SummaryOptions,
+        errors: Vec<(Option<Span>, FlattenError)>,
     }
 
     impl Quantity {
@@ -137,106 +889,405 @@ pub mod calculator {
             self,
             product: &ProductDefinition,
         ) -> Result<NonEmpty<AmountOf<&ProductName>>> {
-            product
-                .ingredients
-                .as_ref()
-                .ok_or_else(|| eyre!("product [{:?}] has no ingredients", product.name))
-                .and_then(
-                    |AmountOf {
-                         quantity,
-                         inner: ingredients,
-                     }| {
-                        ingredients.as_ref().try_map(|ingredient| {
-                            self.ratio(*quantity)
-                                .with_context(|| {
-                                    format!(
-                                        "calculating ratio of [{:?}] in [{:?}]",
-                                        ingredient.inner, product.name
-                                    )
-                                })
-                                .map(|ratio| {
-                                    ingredient.quantity.mul(ratio).pipe(|quantity| AmountOf {
-                                        quantity,
-                                        inner: &ingredient.inner,
+            product.ingredients.pipe_ref(
+                |AmountOf {
+                     quantity,
+                     inner: ingredients,
+                 }| {
+                    ingredients
+                        .as_ref()
+                        .ok_or_else(|| eyre!("product [{:?}] has no ingredients", product.name))
+                        .and_then(|ingredients| {
+                            ingredients.as_ref().try_map(|ingredient| {
+                                product
+                                    .density
+                                    .and_then(|density| density.convert(self, quantity.unit))
+                                    .unwrap_or(self)
+                                    .ratio(*quantity)
+                                    .with_context(|| {
+                                        format!(
+                                            "calculating ratio of [{:?}] in [{:?}]",
+                                            ingredient.inner, product.name
+                                        )
                                     })
-                                })
+                                    .map(|ratio| {
+                                        ingredient.quantity.share_of(*quantity).mul(ratio).pipe(
+                                            |quantity| AmountOf {
+                                                quantity,
+                                                inner: &ingredient.inner,
+                                            },
+                                        )
+                                    })
+                            })
                         })
-                    },
-                )
+                },
+            )
         }
     }
 
     impl<'input> GMDSummaryBuilder<'input> {
+        /// days up to the current one, latest first, followed by the library
+        fn scopes(&self) -> impl Iterator<Item = &GMDDay<'input>> {
+            self.current_day
+                .map(|day| self.current.0.range(..=day))
+                .into_iter()
+                .flatten()
+                .rev()
+                .map(|(_, day)| day)
+                .chain(once(&self.current.1))
+        }
+
+        /// where definitions logged now go
+        fn scope(&mut self) -> &mut GMDDay<'input> {
+            match self.current_day {
+                Some(day) => self.current.0.entry(day).or_default(),
+                None => &mut self.current.1,
+            }
+        }
+
+        /// where eaten products logged now go, see [`SummaryOptions::reference_date`]
+        fn day(&mut self) -> Result<&mut GMDDay<'input>> {
+            self.current_day
+                .or(self.options.reference_date)
+                .ok_or_else(|| eyre!("logged before the first day and no reference date is set"))
+                .map(|day| self.current.0.entry(day).or_default())
+        }
+
         pub fn definition<'name, 'state: 'name>(
             &'state self,
             product_name: &'name ProductName,
         ) -> Option<&'state ProductDefinition> {
-            self.pipe(
-                |Self {
-                     current,
-                     current_day,
-                 }| {
-                    current
-                        .0
-                        .range(..=current_day)
-                        .rev()
-                        .find_map(|(_, day)| day.defined_products.get(product_name).copied())
-                },
-            )
+            self.scopes()
+                .find_map(|day| day.defined_products.get(product_name).copied())
+        }
+
+        /// the latest nutrient or product called `name`, products win within a single day.
+        /// the library is only searched when no day defines it
+        pub fn resolve(&self, name: &ProductName) -> Option<Definition<'input>> {
+            self.scopes().find_map(|day| {
+                day.defined_products
+                    .get(name)
+                    .map(|product| Definition::Product(product))
+                    .or_else(|| {
+                        day.nutrients
+                            .get(name)
+                            .map(|name| Definition::Nutrient(name))
+                    })
+            })
+        }
+
+        /// `definition` and every product definition it's broken down through, each once
+        fn definitions_used(
+            &self,
+            definition: Definition<'input>,
+        ) -> Vec<&'input ProductDefinition> {
+            let mut used: Vec<&'input ProductDefinition> = vec![];
+            let mut pending = vec![definition];
+            while let Some(next) = pending.pop() {
+                match next {
+                    Definition::Product(product)
+                        if !used.iter().any(|seen| std::ptr::eq(*seen, product)) =>
+                    {
+                        used.push(product);
+                        product
+                            .ingredients
+                            .inner
+                            .iter()
+                            .flatten()
+                            .filter_map(|ingredient| self.resolve(&ingredient.inner))
+                            .pipe(|ingredients| pending.extend(ingredients));
+                    }
+                    Definition::Product(_) | Definition::Nutrient(_) => {}
+                }
+            }
+            used
+        }
+
+        /// where `definition` was logged, if the log is spanned
+        fn span_of(&self, definition: &ProductDefinition) -> Option<&'input Span> {
+            self.current
+                .0
+                .values()
+                .chain(once(&self.current.1))
+                .flat_map(|day| day.definitions.iter())
+                .find(|(_, logged)| std::ptr::eq(*logged, definition))
+                .and_then(|(span, _)| *span)
+        }
+
+        /// every product is reported once for each of its definitions, at the definition when
+        /// it's known and at the entry it was found through otherwise
+        fn record(&mut self, entry: Option<&Span>, error: FlattenError) {
+            let span = match self.resolve(error.product()) {
+                Some(Definition::Product(product)) => self.span_of(product),
+                _ => None,
+            }
+            .or(entry)
+            .cloned();
+            if !self.errors.iter().any(|(reported, existing)| {
+                existing.product() == error.product() && *reported == span
+            }) {
+                self.errors.push((span, error));
+            }
+        }
+
+        fn warn_on_redefinition(&self, product: &ProductDefinition) {
+            if let Some(previous) = self.definition(&product.name) {
+                match previous.diff(product) {
+                    Ok(diff) if diff.exceeds(REDEFINITION_THRESHOLD) => {
+                        warn!(product=%product.name, ?diff, "redefinition changes nutrients")
+                    }
+                    Ok(_) => {}
+                    Err(message) => warn!(?message, "comparing redefinition"),
+                }
+            }
         }
+
         pub fn flatten_product_once(
             &self,
             product: &ProductDefinition,
             amount: Quantity,
-        ) -> Result<NonEmpty<AmountOf<&ProductDefinition>>> {
-            amount.of_ingredients(product).and_then(|ingredients| {
+        ) -> Result<NonEmpty<AmountOf<Definition<'_>>>, FlattenError> {
+            match product.ingredients.inner {
+                None => Err(FlattenError::UnknownComposition {
+                    product: product.name.clone(),
+                }),
+                Some(_) => amount.of_ingredients(product).map_err(|message| {
+                    debug!(?message, "calculating ingredients");
+                    FlattenError::IncompatibleQuantity {
+                        product: product.name.clone(),
+                        quantity: amount,
+                        per: product.ingredients.quantity,
+                    }
+                }),
+            }
+            .and_then(|ingredients| {
                 ingredients.try_map(|ingredient| {
                     ingredient.try_map_inner(|name| {
-                        self.definition(name)
-                            .with_context(|| format!("no such product: [{name:?}]"))
+                        self.resolve(name)
+                            .ok_or_else(|| FlattenError::UnknownIngredient {
+                                product: product.name.clone(),
+                                ingredient: name.clone(),
+                            })
                     })
                 })
             })
         }
 
-        pub fn flatten_product<'state, 'product: 'state>(
+        pub fn flatten_product<'state>(
+            &'state self,
+            definition: Definition<'state>,
+            quantity: Quantity,
+        ) -> impl Iterator<Item = Result<AmountOf<&'state ProductName>>> + 'state {
+            self.flatten_product_along(definition, quantity, vec![])
+        }
+
+        /// `path` leads from the eaten product to this one and is how cycles are caught
+        fn flatten_product_along<'state>(
             &'state self,
-            product: &'product ProductDefinition,
+            definition: Definition<'state>,
             quantity: Quantity,
-        ) -> impl Iterator<Item = AmountOf<&'state ProductName>> + 'state {
+            path: Vec<&'state ProductName>,
+        ) -> Box<dyn Iterator<Item = Result<AmountOf<&'state ProductName>>> + 'state> {
+            let name = definition.name();
+            let fail = |error: eyre::Report| error.pipe(Err).pipe(once).pipe(boxed);
+            if let Some(start) = path.iter().position(|visited| *visited == name) {
+                return path[start..]
+                    .iter()
+                    .copied()
+                    .chain(once(name))
+                    .cloned()
+                    .collect_vec()
+                    .pipe(DefinitionCycle)
+                    .pipe(eyre::Report::new)
+                    .pipe(fail);
+            }
+            let path = path.tap_mut(|path| path.push(name));
+            let as_nutrient = || quantity.of(name).pipe(Ok).pipe(once).pipe(boxed);
+            let product = match definition {
+                Definition::Nutrient(_) => return as_nutrient(),
+                Definition::Product(product) => product,
+            };
             match self.flatten_product_once(product, quantity) {
                 Ok(more) => more
                     .into_iter()
-                    .flat_map(
-                        |AmountOf {
-                             quantity,
-                             inner: product,
-                         }| self.flatten_product(product, quantity),
-                    )
+                    .flat_map(move |AmountOf { quantity, inner }| {
+                        self.flatten_product_along(inner, quantity, path.clone())
+                    })
                     .pipe(boxed),
-                Err(message) => {
-                    warn!(?message, "flattening product");
-                    quantity.of(&product.name).pipe(once).pipe(boxed)
+                // defining a product without ingredients used to be the way of declaring a nutrient
+                Err(error @ FlattenError::UnknownComposition { .. })
+                    if self.options.strict == StrictMode::Lenient =>
+                {
+                    debug!(%error, "counting product as a nutrient");
+                    as_nutrient()
                 }
+                Err(error) => match self.options.strict {
+                    StrictMode::Lenient => {
+                        warn!(%error, "flattening product");
+                        as_nutrient()
+                    }
+                    StrictMode::Strict => error.pipe(eyre::Report::new).pipe(fail),
+                },
             }
         }
     }
 
     impl<'input> GMDSummaryBuilder<'input> {
-        pub fn new() -> Self {
+        pub fn new(options: SummaryOptions) -> Self {
             Self {
                 current: Default::default(),
-                current_day: chrono::Local::now().date_naive(),
+                current_day: None,
+                current_meal: None,
+                options,
+                errors: vec![],
             }
         }
     }
 
     impl<'input> GMDSummary<'input> {
         pub fn from_log(log: &'input GMDLog) -> Result<Self> {
+            Self::from_log_with(log, Default::default())
+        }
+
+        /// fails with [`FlattenErrors`] in [`StrictMode::Strict`]
+        pub fn from_log_with(log: &'input GMDLog, options: SummaryOptions) -> Result<Self> {
             log.0
                 .iter()
-                .try_fold(GMDSummaryBuilder::new(), |acc, next| {
-                    let _span = info_span!("handling event", day=%acc.current_day).entered();
+                .map(|entry| (None, entry))
+                .pipe(|entries| Self::from_entries(entries, options))
+        }
+
+        /// products, nutrients and targets in effect on all days
+        pub fn library(&self) -> &GMDDay<'input> {
+            &self.1
+        }
+
+        /// every product defined on any of the days or in the library
+        pub fn products(&self) -> Vec<&'input ProductName> {
+            self.0
+                .values()
+                .chain(once(&self.1))
+                .flat_map(|day| day.defined_products.keys().copied())
+                .unique()
+                .sorted()
+                .collect()
+        }
+
+        /// every definition of `product`, the library one first and then the oldest
+        pub fn versions(&self, product: &ProductName) -> Vec<ProductVersion<'input>> {
+            once((None, &self.1))
+                .chain(self.0.iter().map(|(day, summary)| (Some(*day), summary)))
+                .flat_map(|(day, summary)| {
+                    summary
+                        .definitions
+                        .iter()
+                        .filter(|(_, definition)| definition.name == *product)
+                        .map(move |(span, definition)| (day, *span, *definition))
+                })
+                .collect_vec()
+                .pipe(|versions| {
+                    versions
+                        .iter()
+                        .enumerate()
+                        .map(
+                            |(index, (effective_from, span, definition))| ProductVersion {
+                                definition,
+                                span: *span,
+                                effective_from: *effective_from,
+                                effective_until: versions.get(index + 1).and_then(|(day, ..)| *day),
+                                used_on: self
+                                    .0
+                                    .iter()
+                                    .filter(|(_, summary)| {
+                                        summary.eaten.iter().any(|eaten| {
+                                            eaten
+                                                .definitions
+                                                .iter()
+                                                .any(|used| std::ptr::eq(*used, *definition))
+                                        })
+                                    })
+                                    .map(|(day, _)| *day)
+                                    .collect(),
+                            },
+                        )
+                        .collect()
+                })
+        }
+
+        /// every nutrient declared on any of the days or in the library
+        pub fn nutrients(&self) -> BTreeSet<&'input ProductName> {
+            self.0
+                .values()
+                .chain(once(&self.1))
+                .flat_map(|day| day.nutrients.iter().copied())
+                .collect()
+        }
+
+        /// what was eaten on `day` but isn't a declared nutrient, so its composition is unknown
+        pub fn unknown_composition(
+            &self,
+            day: chrono::NaiveDate,
+        ) -> BTreeMap<&ProductName, Quantity> {
+            let nutrients = self.nutrients();
+            self.0
+                .get(&day)
+                .into_iter()
+                .flat_map(|day| day.state.iter())
+                .filter(|(product, _)| !nutrients.contains(product))
+                .map(|(product, quantity)| (product, *quantity))
+                .collect()
+        }
+
+        /// targets in effect on `day` - set on it, on any of the days before it or in the library
+        pub fn targets(
+            &self,
+            day: chrono::NaiveDate,
+        ) -> BTreeMap<&'input ProductName, &'input Target> {
+            once(&self.1)
+                .chain(self.0.range(..=day).map(|(_, day)| day))
+                .flat_map(|day| day.targets.iter().map(|(name, target)| (*name, *target)))
+                .collect()
+        }
+
+        /// how close the eaten nutrients got to every target in effect on `day`
+        pub fn progress(&self, day: chrono::NaiveDate) -> Result<Vec<TargetProgress<'input>>> {
+            self.targets(day)
+                .into_values()
+                .map(|target| {
+                    self.0
+                        .get(&day)
+                        .and_then(|summary| summary.state.get(&target.product))
+                        .copied()
+                        .pipe(|eaten| TargetProgress::new(target, eaten))
+                        .with_context(|| {
+                            format!("tracking target for [{}] on {day}", target.product)
+                        })
+                })
+                .collect()
+        }
+
+        /// errors are wrapped with the [`Span`] of the entry that caused them
+        pub fn from_spanned_log(log: &'input SpannedGMDLog) -> Result<Self> {
+            Self::from_spanned_log_with(log, Default::default())
+        }
+
+        pub fn from_spanned_log_with(
+            log: &'input SpannedGMDLog,
+            options: SummaryOptions,
+        ) -> Result<Self> {
+            log.0
+                .iter()
+                .map(|Spanned { span, inner }| (Some(span), inner))
+                .pipe(|entries| Self::from_entries(entries, options))
+        }
+
+        fn from_entries(
+            mut entries: impl Iterator<Item = (Option<&'input Span>, &'input LogEntry)>,
+            options: SummaryOptions,
+        ) -> Result<Self> {
+            entries
+                .try_fold(GMDSummaryBuilder::new(options), |acc, (span, next)| {
+                    let _span = info_span!("handling event", day=?acc.current_day).entered();
                     info!(event=?next);
                     match next {
                         LogEntry::StartDay(StartDay(day)) => acc
@@ -245,61 +1296,273 @@ pub mod calculator {
                                     tracing::info!("starting next day");
                                     Default::default()
                                 });
-                                acc.current_day = *day;
+                                acc.current_day = Some(*day);
+                                acc.current_meal = None;
+                            })
+                            .pipe(Ok),
+                        LogEntry::Nutrient(Nutrient(name)) => acc
+                            .tap_mut(|acc| {
+                                acc.scope().nutrients.insert(name);
+                            })
+                            .pipe(Ok),
+                        // included files are loaded as logs of their own
+                        LogEntry::Include(_) => Ok(acc),
+                        LogEntry::Target(target) => acc
+                            .tap_mut(|acc| {
+                                acc.scope().targets.insert(&target.product, target);
                             })
                             .pipe(Ok),
+                        LogEntry::Meal(meal) => acc.pipe(|mut acc| {
+                            acc.day()
+                                .map(|day| {
+                                    day.meal(meal);
+                                })
+                                .map(|_| acc.tap_mut(|acc| acc.current_meal = Some(meal)))
+                        }),
                         LogEntry::Define(product) => acc
                             .tap_mut(|acc| {
-                                acc.current
-                                    .0
-                                    .entry(acc.current_day)
-                                    .or_default()
-                                    .defined_products
-                                    .insert(&product.name, product);
+                                acc.warn_on_redefinition(product);
+                                acc.scope().defined_products.insert(&product.name, product);
+                                acc.scope().definitions.push((span, product));
                             })
                             .pipe(Ok),
-                        LogEntry::Eat(Eat(AmountOf {
-                            quantity,
-                            inner: product_name,
+                        LogEntry::Eat(Eat {
+                            time,
+                            portion,
+                            product: product_name,
+                        })
+                        | LogEntry::Uneat(Uneat(Eat {
+                            time,
+                            portion,
+                            product: product_name,
                         })) => acc
-                            .definition(product_name)
+                            .resolve(product_name)
                             .with_context(|| format!("product [{product_name:?}] is not defined"))
-                            .map(|definition| {
-                                acc.flatten_product(definition, *quantity)
-                                    .map(|inner| inner.map_inner(Clone::clone))
+                            .and_then(|definition| {
+                                definition
+                                    .quantity_of(portion)
+                                    .map(|quantity| match next {
+                                        LogEntry::Uneat(_) => {
+                                            quantity * Ratio(Decimal::NEGATIVE_ONE)
+                                        }
+                                        _ => quantity,
+                                    })
+                                    .map(|quantity| (definition, quantity))
+                            })
+                            .and_then(|(definition, quantity)| {
+                                let used = acc.definitions_used(definition);
+                                acc.flatten_product(definition, quantity)
+                                    .map_ok(|inner| inner.map_inner(Clone::clone))
                                     .collect_vec()
+                                    .into_iter()
+                                    .partition_result::<Vec<_>, Vec<_>, _, _>()
+                                    .pipe(|(eaten, errors)| {
+                                        errors
+                                            .into_iter()
+                                            .map(|report| report.downcast::<FlattenError>())
+                                            .collect::<Result<Vec<_>>>()
+                                            .map(|errors| (eaten, errors, used))
+                                    })
                             })
-                            .and_then(|eaten| {
+                            .and_then(|(eaten, errors, used)| {
                                 acc.pipe(|mut acc| {
-                                    eaten
-                                        .into_iter()
-                                        .try_for_each(
-                                            |AmountOf {
-                                                 quantity,
-                                                 inner: product_name,
-                                             }| {
-                                                acc.current
-                                                    .0
-                                                    .entry(acc.current_day)
-                                                    .or_default()
-                                                    .state
-                                                    .entry(product_name)
-                                                    .or_insert_with(|| Quantity {
-                                                        amount: Default::default(),
-                                                        unit: quantity.unit,
+                                    errors.into_iter().for_each(|error| acc.record(span, error));
+                                    let meal = acc.current_meal;
+                                    acc.day()
+                                        .and_then(|day| {
+                                            eaten
+                                                .into_iter()
+                                                .try_fold(BTreeMap::new(), |mut added, eaten| {
+                                                    add_eaten(&mut day.state, eaten.clone())
+                                                        .and_then(|_| match meal {
+                                                            Some(meal) => add_eaten(
+                                                                &mut day.meal(meal).state,
+                                                                eaten.clone(),
+                                                            ),
+                                                            None => Ok(()),
+                                                        })
+                                                        .and_then(|_| add_eaten(&mut added, eaten))
+                                                        .map(|_| added)
+                                                })
+                                                .map(|added| {
+                                                    day.eaten.push(GMDEaten {
+                                                        entry: next,
+                                                        time: time.or_else(|| {
+                                                            meal.and_then(|meal| meal.time)
+                                                        }),
+                                                        state: added,
+                                                        definitions: used,
                                                     })
-                                                    .try_add(quantity)
-                                            },
-                                        )
+                                                })
+                                        })
                                         .map(|_| acc)
                                 })
                             }),
                     }
+                    .pipe(|result| match span {
+                        Some(span) => result.wrap_err_with(|| span.clone()),
+                        None => result,
+                    })
                 })
-                .map(|GMDSummaryBuilder { current, .. }| current)
+                .and_then(
+                    |GMDSummaryBuilder {
+                         current, errors, ..
+                     }| match errors.is_empty() {
+                        true => Ok(current),
+                        false => Err(FlattenErrors(errors).into()),
+                    },
+                )
         }
     }
 }
 
+pub mod formatter {
+    use crate::{
+        models::GMDLog,
+        parser::{FromGMD, ToGMD},
+    };
+    use eyre::{Result, WrapErr};
+
+    /// canonical form of a log - consistent indentation, empty lines, decimals, units and `\n`
+    /// line endings
+    pub fn format(input: &str) -> Result<String> {
+        GMDLog::from_gmd(&input.replace("\r\n", "\n"))
+            .map(|log| log.to_gmd())
+            .wrap_err("formatting log")
+    }
+
+    pub fn is_formatted(input: &str) -> Result<bool> {
+        format(input).map(|formatted| formatted == input)
+    }
+}
+
+pub mod merge {
+    use crate::{
+        error::Located,
+        models::{FileId, LogEntry, Nutrient, ProductName, Span, Spanned, SpannedGMDLog, StartDay},
+    };
+    use eyre::Result;
+    use itertools::Itertools;
+    use std::collections::BTreeMap;
+    use tap::prelude::*;
+
+    /// entries logged on a single day of a single file, starting with its [`StartDay`]
+    type Day = (Option<chrono::NaiveDate>, Vec<Spanned<LogEntry>>);
+
+    /// the same product defined differently by several files on the same day
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct DefinitionConflict {
+        pub product: ProductName,
+        /// `None` when it's defined before the first day, in the library
+        pub day: Option<chrono::NaiveDate>,
+        /// the last definition from each of the files
+        pub definitions: Vec<Span>,
+    }
+
+    impl std::fmt::Display for DefinitionConflict {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self.day {
+                Some(day) => write!(f, "[{}] is defined differently on {day}", self.product),
+                None => write!(
+                    f,
+                    "[{}] is defined differently in the library",
+                    self.product
+                ),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct DefinitionConflicts(pub Vec<DefinitionConflict>);
+
+    impl Located for DefinitionConflicts {
+        fn heading(&self) -> String {
+            format!("{} conflicting definition(s)", self.0.len())
+        }
+
+        fn problems(&self) -> Vec<(String, Vec<&Span>)> {
+            self.0
+                .iter()
+                .map(|conflict| (conflict.to_string(), conflict.definitions.iter().collect()))
+                .collect()
+        }
+    }
+
+    impl std::fmt::Display for DefinitionConflicts {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str(&self.located(Span::to_string))
+        }
+    }
+
+    impl std::error::Error for DefinitionConflicts {}
+
+    fn days(SpannedGMDLog(entries): SpannedGMDLog) -> Vec<Day> {
+        entries
+            .into_iter()
+            .fold(vec![(None, vec![])], |days: Vec<Day>, entry| {
+                days.tap_mut(|days| {
+                    if let LogEntry::StartDay(StartDay(day)) = entry.inner {
+                        days.push((Some(day), vec![]));
+                    }
+                    if let Some((_, entries)) = days.last_mut() {
+                        entries.push(entry);
+                    }
+                })
+            })
+    }
+
+    fn conflicts(days: &[Day]) -> Vec<DefinitionConflict> {
+        days.iter()
+            .flat_map(|(day, entries)| {
+                entries
+                    .iter()
+                    .filter_map(move |Spanned { span, inner }| match inner {
+                        LogEntry::Define(product) => Some(((*day, &product.name), (span, inner))),
+                        LogEntry::Nutrient(Nutrient(name)) => Some(((*day, name), (span, inner))),
+                        _ => None,
+                    })
+            })
+            .fold(
+                BTreeMap::<_, BTreeMap<FileId, _>>::new(),
+                |definitions, (key, (span, inner))| {
+                    definitions.tap_mut(|definitions| {
+                        definitions
+                            .entry(key)
+                            .or_default()
+                            .insert(span.file, (span, inner));
+                    })
+                },
+            )
+            .into_iter()
+            .filter(|(_, files)| !files.values().map(|(_, inner)| inner).all_equal())
+            .map(|((day, product), files)| DefinitionConflict {
+                product: product.clone(),
+                day,
+                definitions: files.into_values().map(|(span, _)| span.clone()).collect(),
+            })
+            .collect()
+    }
+
+    /// combines logs of many files by date - entries logged before the first day of any file go
+    /// first, followed by the days in order. entries of a single day keep the order of the files
+    /// and the order they were logged in.
+    /// fails with [`DefinitionConflicts`] when files disagree on what a product is on some day
+    pub fn merge(logs: impl IntoIterator<Item = SpannedGMDLog>) -> Result<SpannedGMDLog> {
+        logs.into_iter()
+            .flat_map(days)
+            .sorted_by_key(|(day, _)| *day)
+            .collect_vec()
+            .pipe(|days| match conflicts(&days) {
+                conflicts if conflicts.is_empty() => days
+                    .into_iter()
+                    .flat_map(|(_, entries)| entries)
+                    .collect_vec()
+                    .pipe(SpannedGMDLog)
+                    .pipe(Ok),
+                conflicts => Err(DefinitionConflicts(conflicts).into()),
+            })
+    }
+}
+
 #[cfg(test)]
 mod tests;
@@ -1,6 +1,8 @@
 use eyre::{eyre, Result};
+use nom::Offset;
 use nonempty::NonEmpty;
 use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
 use serde::{Deserialize, Serialize};
 use tap::prelude::*;
 
@@ -22,6 +24,97 @@ impl std::fmt::Display for Kcal {
     }
 }
 
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Clone, Copy, Default)]
+pub struct Kilojoule;
+
+impl std::fmt::Display for Kilojoule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "kJ")
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Clone, Copy, Default)]
+pub struct Millilitre;
+
+impl std::fmt::Display for Millilitre {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ml")
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Clone, Copy, Default)]
+pub struct Kilogram;
+
+impl std::fmt::Display for Kilogram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "kg")
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Clone, Copy, Default)]
+pub struct Ounce;
+
+impl std::fmt::Display for Ounce {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "oz")
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Clone, Copy, Default)]
+pub struct Pound;
+
+impl std::fmt::Display for Pound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "lb")
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Clone, Copy, Default)]
+pub struct Litre;
+
+impl std::fmt::Display for Litre {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "l")
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Clone, Copy, Default)]
+pub struct Cup;
+
+impl std::fmt::Display for Cup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "cup")
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Clone, Copy, Default)]
+pub struct Tablespoon;
+
+impl std::fmt::Display for Tablespoon {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "tbsp")
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Clone, Copy, Default)]
+pub struct Teaspoon;
+
+impl std::fmt::Display for Teaspoon {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "tsp")
+    }
+}
+
+/// share of the declared quantity of a product, only meaningful for ingredients
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Clone, Copy, Default)]
+pub struct Percent;
+
+impl std::fmt::Display for Percent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "%")
+    }
+}
+
 #[derive(
     Debug,
     Eq,
@@ -36,6 +129,44 @@ impl std::fmt::Display for Kcal {
 pub enum UnitOfMeasure {
     Gram(Gram),
     Kcal(Kcal),
+    Kilojoule(Kilojoule),
+    Millilitre(Millilitre),
+    Percent(Percent),
+    Kilogram(Kilogram),
+    Ounce(Ounce),
+    Pound(Pound),
+    Litre(Litre),
+    Cup(Cup),
+    Tablespoon(Tablespoon),
+    Teaspoon(Teaspoon),
+}
+
+impl UnitOfMeasure {
+    const KILO: Decimal = dec!(1000);
+    const OUNCE: Decimal = dec!(28.349523125);
+    const POUND: Decimal = dec!(453.59237);
+    // metric cup and spoons
+    const CUP: Decimal = dec!(250);
+    const TABLESPOON: Decimal = dec!(15);
+    const TEASPOON: Decimal = dec!(5);
+
+    /// the unit this one is a multiple of, and how many of it one of this unit is
+    pub fn base(self) -> (Decimal, Self) {
+        match self {
+            UnitOfMeasure::Kilogram(_) => (Self::KILO, Gram.into()),
+            UnitOfMeasure::Ounce(_) => (Self::OUNCE, Gram.into()),
+            UnitOfMeasure::Pound(_) => (Self::POUND, Gram.into()),
+            UnitOfMeasure::Litre(_) => (Self::KILO, Millilitre.into()),
+            UnitOfMeasure::Cup(_) => (Self::CUP, Millilitre.into()),
+            UnitOfMeasure::Tablespoon(_) => (Self::TABLESPOON, Millilitre.into()),
+            UnitOfMeasure::Teaspoon(_) => (Self::TEASPOON, Millilitre.into()),
+            UnitOfMeasure::Gram(_)
+            | UnitOfMeasure::Kcal(_)
+            | UnitOfMeasure::Kilojoule(_)
+            | UnitOfMeasure::Millilitre(_)
+            | UnitOfMeasure::Percent(_) => (Decimal::ONE, self),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
@@ -51,13 +182,50 @@ impl std::fmt::Display for Quantity {
 }
 
 impl Quantity {
+    const KILOJOULES_PER_KCAL: Decimal = dec!(4.184);
+
+    /// the same quantity expressed in `unit`, `None` if they measure different things
+    pub fn convert_to(self, unit: UnitOfMeasure) -> Option<Self> {
+        let ((from_factor, from), (to_factor, to)) = (self.unit.base(), unit.base());
+        match (self.unit, unit) {
+            (from, to) if from == to => Some(self.amount),
+            _ => match (from, to) {
+                (from, to) if from == to => Some(self.amount * from_factor / to_factor),
+                (UnitOfMeasure::Kcal(_), UnitOfMeasure::Kilojoule(_)) => {
+                    Some(self.amount * Self::KILOJOULES_PER_KCAL)
+                }
+                (UnitOfMeasure::Kilojoule(_), UnitOfMeasure::Kcal(_)) => {
+                    Some(self.amount / Self::KILOJOULES_PER_KCAL)
+                }
+                _ => None,
+            },
+        }
+        .map(|amount| Self { amount, unit })
+    }
+
+    /// `1lb` as `453.59237g`
+    pub fn to_base(self) -> Self {
+        self.unit.base().pipe(|(factor, unit)| Self {
+            amount: self.amount * factor,
+            unit,
+        })
+    }
+
+    /// quantities in different units are summed up in their base unit
     pub fn try_add(&mut self, other: Self) -> Result<()> {
-        match (self.unit, other.unit) {
-            (one, two) if one == two => {
+        if self.unit != other.unit {
+            *self = self.to_base();
+        }
+        match other.convert_to(self.unit) {
+            Some(other) => {
                 self.amount += other.amount;
                 Ok(())
             }
-            (one, two) => Err(eyre!("incompatible units of measure: [{one}, {two}]")),
+            None => Err(eyre!(
+                "incompatible units of measure: [{}, {}]",
+                self.unit,
+                other.unit
+            )),
         }
     }
     pub fn of<T>(self, inner: T) -> AmountOf<T> {
@@ -106,32 +274,189 @@ impl<Inner> AmountOf<Inner> {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// `density 1ml = 1.03g` - how much a given volume of a product weighs
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct Density {
+    pub volume: Quantity,
+    pub mass: Quantity,
+}
+
+impl Density {
+    /// accepts the sides in either order
+    pub fn new(left: Quantity, right: Quantity) -> Result<Self> {
+        match (left.unit.base().1, right.unit.base().1) {
+            (UnitOfMeasure::Millilitre(_), UnitOfMeasure::Gram(_)) => Ok(Self {
+                volume: left,
+                mass: right,
+            }),
+            (UnitOfMeasure::Gram(_), UnitOfMeasure::Millilitre(_)) => Ok(Self {
+                volume: right,
+                mass: left,
+            }),
+            _ => Err(eyre!(
+                "density relates a volume to a mass, got [{}, {}]",
+                left.unit,
+                right.unit
+            )),
+        }
+        .and_then(|density: Self| {
+            match density.volume.amount.is_zero() || density.mass.amount.is_zero() {
+                true => Err(eyre!("density cannot be zero")),
+                false => Ok(density),
+            }
+        })
+    }
+
+    /// moves `quantity` between volume and mass, `None` if it's neither of them
+    pub fn convert(&self, quantity: Quantity, unit: UnitOfMeasure) -> Option<Quantity> {
+        let (volume, mass, base) = (
+            self.volume.to_base(),
+            self.mass.to_base(),
+            quantity.to_base(),
+        );
+        match (base.unit, unit.base().1) {
+            (from, to) if from == to => Some(base),
+            (UnitOfMeasure::Millilitre(_), UnitOfMeasure::Gram(_)) => Some(Quantity {
+                amount: base.amount * mass.amount / volume.amount,
+                unit: mass.unit,
+            }),
+            (UnitOfMeasure::Gram(_), UnitOfMeasure::Millilitre(_)) => Some(Quantity {
+                amount: base.amount * volume.amount / mass.amount,
+                unit: volume.unit,
+            }),
+            _ => None,
+        }
+        .and_then(|converted| converted.convert_to(unit))
+    }
+}
+
+/// `serving slice = 35g` - a countable portion of a product
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Serving {
+    pub name: String,
+    pub quantity: Quantity,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct ProductDefinition {
     pub name: ProductName,
-    pub ingredients: Option<AmountOf<NonEmpty<AmountOf<ProductName>>>>,
+    /// the quantity the ingredients are given for, kept when the composition of the product is
+    /// not known (`inner` is `None`) so that `define 1kcal of X` is written back as it was
+    pub ingredients: AmountOf<Option<NonEmpty<AmountOf<ProductName>>>>,
+    pub density: Option<Density>,
+    pub servings: Vec<Serving>,
 }
 
 impl ProductDefinition {
     pub fn primitive(name: impl Into<String>) -> Self {
         Self {
             name: ProductName::new(name),
-            ingredients: None,
+            ingredients: Quantity {
+                amount: Decimal::ONE,
+                unit: Gram.into(),
+            }
+            .of(None),
+            density: None,
+            servings: vec![],
+        }
+    }
+
+    pub fn serving(&self, name: &str) -> Option<&Serving> {
+        self.servings.iter().find(|serving| serving.name == name)
+    }
+}
+
+/// `nutrient Białko` - something products are broken down into, which has no ingredients itself
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Nutrient(pub ProductName);
+
+/// `include "foods/vegetables.gmd"` - another log, its path relative to the including file
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Include(pub std::path::PathBuf);
+
+/// how much of a product was eaten
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, derive_more::From)]
+pub enum Portion {
+    /// `400g`
+    Quantity(Quantity),
+    /// `2 slice`, resolved through the servings of the product
+    Servings { count: Decimal, serving: String },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Eat {
+    /// `12:30 eat ...`
+    pub time: Option<chrono::NaiveTime>,
+    pub portion: Portion,
+    pub product: ProductName,
+}
+
+impl From<AmountOf<ProductName>> for Eat {
+    fn from(AmountOf { quantity, inner }: AmountOf<ProductName>) -> Self {
+        Self {
+            time: None,
+            portion: quantity.into(),
+            product: inner,
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Eat(pub AmountOf<ProductName>);
+/// `uneat 50g of X` - correction which takes back a previous [`Eat`]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Uneat(pub Eat);
 
-#[derive(Debug, Serialize, Deserialize)]
+/// `08:00 meal Śniadanie` - groups the eat entries which follow it, up to the next meal or day
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Meal {
+    pub time: Option<chrono::NaiveTime>,
+    pub name: String,
+}
+
+/// `target 150g..200g of Białko` - how much of a product should be eaten every day,
+/// from the day it's logged on until it's replaced
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub lower: Quantity,
+    /// `None` when a single value is the target
+    pub upper: Option<Quantity>,
+    pub product: ProductName,
+}
+
+impl Target {
+    /// bounds have to be positive, the upper one no lower than the lower one
+    pub fn new(lower: Quantity, upper: Option<Quantity>, product: ProductName) -> Result<Self> {
+        match upper.map(|upper| (upper.convert_to(lower.unit), upper)) {
+            _ if lower.amount <= Decimal::ZERO => {
+                Err(eyre!("target has to be above zero: [{lower}]"))
+            }
+            Some((None, upper)) => Err(eyre!(
+                "target bounds measure different things: [{lower}, {upper}]"
+            )),
+            Some((Some(converted), upper)) if converted.amount < lower.amount => Err(eyre!(
+                "upper bound of a target is below its lower bound: [{lower}, {upper}]"
+            )),
+            _ => Ok(Self {
+                lower,
+                upper,
+                product,
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct StartDay(pub chrono::NaiveDate);
 
-#[derive(Debug, Serialize, Deserialize, derive_more::From)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, derive_more::From)]
 pub enum LogEntry {
     StartDay(StartDay),
+    Include(Include),
     Define(ProductDefinition),
+    Nutrient(Nutrient),
     Eat(Eat),
+    Uneat(Uneat),
+    Meal(Meal),
+    Target(Target),
 }
 
 /// define 30g of Pasibus Avocadus:
@@ -140,9 +465,74 @@ pub enum LogEntry {
 /// 2024-01-20
 /// eat Pasibus Avocadus
 /// eat 50g of Jogurt grecki lidl
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct GMDLog(pub Vec<LogEntry>);
 
+/// index of a source file in whatever loaded it
+#[derive(
+    Debug,
+    Serialize,
+    Deserialize,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Default,
+    derive_more::Display,
+)]
+#[display(fmt = "#{}", _0)]
+pub struct FileId(pub usize);
+
+/// location of an entry in its source file
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub file: FileId,
+    /// byte range
+    pub range: std::ops::Range<usize>,
+    /// 1-based line the entry starts on
+    pub line: usize,
+}
+
+impl Span {
+    /// `fragment` must be a slice of `source`
+    pub fn of(file: FileId, source: &str, fragment: &str) -> Self {
+        source.offset(fragment).pipe(|start| Self {
+            file,
+            range: start..(start + fragment.len()),
+            line: source[..start].matches('\n').count() + 1,
+        })
+    }
+}
+
+impl std::fmt::Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "file {}, line {}", self.file, self.line)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Spanned<Inner> {
+    pub span: Span,
+    pub inner: Inner,
+}
+
+/// [`GMDLog`] which remembers where each of the entries came from
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct SpannedGMDLog(pub Vec<Spanned<LogEntry>>);
+
+impl From<SpannedGMDLog> for GMDLog {
+    fn from(SpannedGMDLog(entries): SpannedGMDLog) -> Self {
+        entries
+            .into_iter()
+            .map(|Spanned { inner, .. }| inner)
+            .collect::<Vec<_>>()
+            .pipe(Self)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -218,13 +608,15 @@ mod tests {
                             )
                             .collect_vec()
                             .pipe(NonEmpty::from_vec)
-                            .map(|per_100g| g(100.).of(per_100g)),
+                            .pipe(|per_100g| g(100.).of(per_100g)),
+                        density: None,
+                        servings: vec![],
                     }
                     .pipe(once)
                     .map(LogEntry::from),
                 )
                 .chain(
-                    Eat(g(100.).of("Frytki".pipe(ProductName::new)))
+                    Eat::from(g(100.).of("Frytki".pipe(ProductName::new)))
                         .pipe(once)
                         .map(LogEntry::from),
                 )
@@ -1,22 +1,27 @@
 use self::helpers::{surrounded_by_whitespace, whitespace};
 use crate::{
-    error::Res,
+    error::{ParseDiagnostic, Res},
     models::{
-        AmountOf, Eat, GMDLog, Gram, Kcal, LogEntry, ProductDefinition, ProductName, Quantity,
-        StartDay, UnitOfMeasure,
+        AmountOf, Cup, Density, Eat, FileId, GMDLog, Gram, Include, Kcal, Kilogram, Kilojoule,
+        Litre, LogEntry, Meal, Millilitre, Nutrient, Ounce, Percent, Portion, Pound,
+        ProductDefinition, ProductName, Quantity, Serving, Span, Spanned, SpannedGMDLog, StartDay,
+        Tablespoon, Target, Teaspoon, Uneat, UnitOfMeasure,
     },
     type_name,
 };
-use chrono::NaiveDate;
+use chrono::{NaiveDate, NaiveTime};
 use eyre::{eyre, Result, WrapErr};
 use nom::{
     branch::alt,
     bytes::complete::take_while1,
-    multi::{many1, separated_list1},
+    combinator::eof,
+    multi::{many0, many1},
     sequence::{separated_pair, tuple},
     Parser,
 };
-use nom_supreme::{tag::complete::tag, ParserExt};
+use nom_supreme::{
+    error::ErrorTree, multi::collect_separated_terminated, tag::complete::tag, ParserExt,
+};
 use nonempty::NonEmpty;
 use rust_decimal::Decimal;
 use rust_decimal_macros::dec;
@@ -32,7 +37,7 @@ pub trait FromGMD: Sized {
             .complete()
             .all_consuming()
             .parse(input.trim())
-            .map_err(|e| eyre!("{e:?}"))
+            .map_err(|e| ParseDiagnostic::report(input, e))
             .map(|(_, v)| v)
             .with_context(|| format!("Parsing [{}]", crate::type_name!()))
     }
@@ -57,28 +62,122 @@ impl FromGMD for Kcal {
     }
 }
 
-impl FromGMD for UnitOfMeasure {
+impl FromGMD for Kilojoule {
+    #[tracing::instrument(skip(input), ret(level = Level::TRACE))]
+    fn parse(input: &str) -> Res<'_, Self> {
+        tag("kJ").map(|_| Self).context(type_name!()).parse(input)
+    }
+}
+
+impl FromGMD for Millilitre {
+    #[tracing::instrument(skip(input), ret(level = Level::TRACE))]
+    fn parse(input: &str) -> Res<'_, Self> {
+        tag("ml").map(|_| Self).context(type_name!()).parse(input)
+    }
+}
+
+impl FromGMD for Kilogram {
+    #[tracing::instrument(skip(input), ret(level = Level::TRACE))]
+    fn parse(input: &str) -> Res<'_, Self> {
+        tag("kg").map(|_| Self).context(type_name!()).parse(input)
+    }
+}
+
+impl FromGMD for Ounce {
+    #[tracing::instrument(skip(input), ret(level = Level::TRACE))]
+    fn parse(input: &str) -> Res<'_, Self> {
+        tag("oz").map(|_| Self).context(type_name!()).parse(input)
+    }
+}
+
+impl FromGMD for Pound {
+    #[tracing::instrument(skip(input), ret(level = Level::TRACE))]
+    fn parse(input: &str) -> Res<'_, Self> {
+        tag("lb").map(|_| Self).context(type_name!()).parse(input)
+    }
+}
+
+impl FromGMD for Litre {
+    #[tracing::instrument(skip(input), ret(level = Level::TRACE))]
+    fn parse(input: &str) -> Res<'_, Self> {
+        tag("l").map(|_| Self).context(type_name!()).parse(input)
+    }
+}
+
+impl FromGMD for Cup {
+    #[tracing::instrument(skip(input), ret(level = Level::TRACE))]
+    fn parse(input: &str) -> Res<'_, Self> {
+        tag("cup").map(|_| Self).context(type_name!()).parse(input)
+    }
+}
+
+impl FromGMD for Tablespoon {
+    #[tracing::instrument(skip(input), ret(level = Level::TRACE))]
+    fn parse(input: &str) -> Res<'_, Self> {
+        tag("tbsp").map(|_| Self).context(type_name!()).parse(input)
+    }
+}
+
+impl FromGMD for Teaspoon {
     #[tracing::instrument(skip(input), ret(level = Level::TRACE))]
     fn parse(input: &str) -> Res<'_, Self> {
-        alt((Gram::parse.map(Self::from), Kcal::parse.map(Self::from)))
+        tag("tsp").map(|_| Self).context(type_name!()).parse(input)
+    }
+}
+
+impl FromGMD for Percent {
+    #[tracing::instrument(skip(input), ret(level = Level::TRACE))]
+    fn parse(input: &str) -> Res<'_, Self> {
+        keyword::PERCENT::tag
+            .map(|_| Self)
             .context(type_name!())
             .parse(input)
     }
 }
 
+impl FromGMD for UnitOfMeasure {
+    #[tracing::instrument(skip(input), ret(level = Level::TRACE))]
+    fn parse(input: &str) -> Res<'_, Self> {
+        alt((
+            Gram::parse.map(Self::from),
+            Kcal::parse.map(Self::from),
+            Kilojoule::parse.map(Self::from),
+            Millilitre::parse.map(Self::from),
+            Kilogram::parse.map(Self::from),
+            Ounce::parse.map(Self::from),
+            // before litres, which it starts with
+            Pound::parse.map(Self::from),
+            Litre::parse.map(Self::from),
+            Cup::parse.map(Self::from),
+            Tablespoon::parse.map(Self::from),
+            Teaspoon::parse.map(Self::from),
+        ))
+        .context(type_name!())
+        .parse(input)
+    }
+}
+
 impl FromGMD for Decimal {
     #[tracing::instrument(skip(input), ret(level = Level::TRACE))]
     fn parse(input: &str) -> Res<'_, Self> {
-        const LEGAL: &[char] = &['-', '.', '0', '1', '2', '3', '4', '5', '6', '7', '8', '9'];
+        const LEGAL: &[char] = &['.', '0', '1', '2', '3', '4', '5', '6', '7', '8', '9'];
 
-        take_while1(|c: char| LEGAL.contains(&c))
-            .map_res(Decimal::from_str_exact)
-            .context(type_name!())
-            .parse(input)
+        tuple((
+            keyword::MINUS::tag.opt(),
+            take_while1(|c: char| LEGAL.contains(&c)).map_res(Decimal::from_str_exact),
+        ))
+        .map(|(minus, value)| match minus {
+            Some(_) => -value,
+            None => value,
+        })
+        .context(type_name!())
+        .parse(input)
     }
 }
 
-enum SpecialUnitOfMeasure {
+/// written as grams when parsed, and picked for small amounts of grams when written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpecialUnitOfMeasure {
     Milligram,
     Microgram,
 }
@@ -86,18 +185,45 @@ enum SpecialUnitOfMeasure {
 impl SpecialUnitOfMeasure {
     const MILI: Decimal = dec!(0.001);
     const MICRO: Decimal = dec!(0.000001);
+    const STEP_DOWN: Decimal = dec!(0.1);
+
+    /// how many grams one of this unit is
+    fn factor(&self) -> Decimal {
+        match self {
+            SpecialUnitOfMeasure::Milligram => Self::MILI,
+            SpecialUnitOfMeasure::Microgram => Self::MICRO,
+        }
+    }
 
     pub fn convert(self, amount: Decimal) -> Quantity {
+        Quantity {
+            amount: amount * self.factor(),
+            unit: Gram.into(),
+        }
+    }
+
+    /// amounts below a tenth of a gram (or a tenth of a milligram) read better in a smaller unit
+    fn for_grams(amount: Decimal) -> Option<Self> {
+        match amount.abs() {
+            amount if amount.is_zero() || amount >= Self::STEP_DOWN => None,
+            amount if amount >= Self::STEP_DOWN * Self::MILI => Some(Self::Milligram),
+            _ => Some(Self::Microgram),
+        }
+    }
+
+    /// `amount` of grams in this unit
+    pub fn convert_grams(&self, amount: Decimal) -> Decimal {
+        amount / self.factor()
+    }
+}
+
+impl ToGMD for SpecialUnitOfMeasure {
+    fn to_gmd(&self) -> String {
         match self {
-            SpecialUnitOfMeasure::Milligram => Quantity {
-                amount: amount * Self::MILI,
-                unit: Gram.into(),
-            },
-            SpecialUnitOfMeasure::Microgram => Quantity {
-                amount: amount * Self::MICRO,
-                unit: Gram.into(),
-            },
+            SpecialUnitOfMeasure::Milligram => "mg",
+            SpecialUnitOfMeasure::Microgram => "µg",
         }
+        .to_string()
     }
 }
 
@@ -186,6 +312,16 @@ impl FromGMD for NaiveDate {
     }
 }
 
+impl FromGMD for NaiveTime {
+    #[tracing::instrument(skip(input), ret(level = Level::TRACE))]
+    fn parse(input: &str) -> Res<'_, Self> {
+        take_while1(|c: char| c == ':' || c.is_ascii_digit())
+            .map_res(|input| NaiveTime::parse_from_str(input, "%H:%M"))
+            .context(type_name!())
+            .parse(input)
+    }
+}
+
 impl FromGMD for StartDay {
     #[tracing::instrument(skip(input), ret(level = Level::TRACE))]
     fn parse(input: &str) -> Res<'_, Self> {
@@ -200,21 +336,139 @@ impl FromGMD for ProductName {
     #[tracing::instrument(skip(input), ret(level = Level::TRACE))]
     fn parse(input: &str) -> Res<'_, Self> {
         take_while1(|c: char| c != '\n')
-            .map(|v: &str| v.to_string().pipe(Self))
+            .map(|v: &str| v.trim_end().to_string().pipe(Self))
             .context(type_name!())
             .parse(input)
     }
 }
 
+impl FromGMD for Density {
+    #[tracing::instrument(skip(input), ret(level = Level::TRACE))]
+    fn parse(input: &str) -> Res<'_, Self> {
+        separated_pair(
+            Quantity::parse,
+            keyword::EQUALS::tag.pipe(surrounded_by_whitespace),
+            Quantity::parse,
+        )
+        .preceded_by(keyword::DENSITY::tag.terminated(whitespace))
+        .map_res(|(left, right)| Density::new(left, right))
+        .context(type_name!())
+        .parse(input)
+    }
+}
+
+/// name of a serving, a single word such as `slice` which isn't a unit
+fn serving_name(input: &str) -> Res<'_, String> {
+    take_while1(|c: char| !c.is_whitespace())
+        .verify(|name: &&str| {
+            UnitOfMeasure::from_gmd(name).is_err() && SpecialUnitOfMeasure::from_gmd(name).is_err()
+        })
+        .map(String::from)
+        .context("serving name")
+        .parse(input)
+}
+
+impl FromGMD for Nutrient {
+    #[tracing::instrument(skip(input), ret(level = Level::TRACE))]
+    fn parse(input: &str) -> Res<'_, Self> {
+        ProductName::parse
+            .preceded_by(keyword::NUTRIENT::tag.terminated(whitespace))
+            .map(Self)
+            .context(type_name!())
+            .parse(input)
+    }
+}
+
+impl FromGMD for Include {
+    #[tracing::instrument(skip(input), ret(level = Level::TRACE))]
+    fn parse(input: &str) -> Res<'_, Self> {
+        take_while1(|c| c != '"' && c != '\n')
+            .preceded_by(keyword::QUOTE::tag)
+            .terminated(keyword::QUOTE::tag)
+            .preceded_by(keyword::INCLUDE::tag.terminated(whitespace))
+            .map(|path: &str| Self(path.into()))
+            .context(type_name!())
+            .parse(input)
+    }
+}
+
+impl FromGMD for Serving {
+    #[tracing::instrument(skip(input), ret(level = Level::TRACE))]
+    fn parse(input: &str) -> Res<'_, Self> {
+        separated_pair(
+            serving_name,
+            keyword::EQUALS::tag.pipe(surrounded_by_whitespace),
+            Quantity::parse,
+        )
+        .preceded_by(keyword::SERVING::tag.terminated(whitespace))
+        .map(|(name, quantity)| Self { name, quantity })
+        .context(type_name!())
+        .parse(input)
+    }
+}
+
+/// `- 12% of Białko` - unlike any other quantity, an ingredient can be a share of the declared
+/// quantity of the product
+fn ingredient(input: &str) -> Res<'_, AmountOf<ProductName>> {
+    separated_pair(
+        alt((
+            Quantity::parse,
+            tuple((Decimal::parse, Percent::parse)).map(|(amount, percent)| Quantity {
+                amount,
+                unit: percent.into(),
+            }),
+        )),
+        keyword::OF::tag.pipe(surrounded_by_whitespace),
+        ProductName::parse,
+    )
+    .map(|(quantity, inner)| AmountOf { quantity, inner })
+    .context("ingredient")
+    .parse(input)
+}
+
+/// a line following the ingredients of a definition
+enum DefinitionDetail {
+    Density(Density),
+    Serving(Serving),
+}
+
+/// `density` and `serving` lines in any order, with at most one density
+fn definition_details(input: &str) -> Res<'_, (Option<Density>, Vec<Serving>)> {
+    alt((
+        Density::parse.map(DefinitionDetail::Density),
+        Serving::parse.map(DefinitionDetail::Serving),
+    ))
+    .preceded_by(whitespace)
+    .pipe(many0)
+    .map_res(|details| {
+        details
+            .into_iter()
+            .try_fold((None, vec![]), |(density, servings), detail| {
+                match (detail, density) {
+                    (DefinitionDetail::Density(_), Some(_)) => {
+                        Err(eyre!("density can only be given once"))
+                    }
+                    (DefinitionDetail::Density(density), None) => Ok((Some(density), servings)),
+                    (DefinitionDetail::Serving(serving), density) => {
+                        Ok((density, servings.tap_mut(|servings| servings.push(serving))))
+                    }
+                }
+            })
+    })
+    .context("density and servings")
+    .parse(input)
+}
+
 impl FromGMD for ProductDefinition {
     #[tracing::instrument(skip(input), ret(level = Level::TRACE))]
     fn parse(input: &str) -> Res<'_, Self> {
         tuple((
             AmountOf::<ProductName>::parse.preceded_by(keyword::DEFINE::tag.terminated(whitespace)),
-            AmountOf::<ProductName>::parse
+            ingredient
                 .preceded_by(tag("-").pipe(surrounded_by_whitespace))
                 .pipe(many1)
                 .opt(),
+            definition_details,
         ))
         .map(
             |(
@@ -223,12 +477,15 @@ impl FromGMD for ProductDefinition {
                     inner: name,
                 },
                 ingredients,
+                (density, servings),
             )| {
                 ingredients
                     .and_then(NonEmpty::from_vec)
                     .pipe(|ingredients| ProductDefinition {
                         name,
-                        ingredients: ingredients.map(|ingredients| quantity.of(ingredients)),
+                        ingredients: quantity.of(ingredients),
+                        density,
+                        servings,
                     })
             },
         )
@@ -237,23 +494,120 @@ impl FromGMD for ProductDefinition {
     }
 }
 
+/// `5 lb` - a quantity with a space before its unit, which would otherwise read as a serving
+fn spaced_quantity(input: &str) -> Res<'_, Quantity> {
+    alt((
+        separated_pair(Decimal::parse, whitespace, SpecialUnitOfMeasure::parse)
+            .map(|(amount, unit)| unit.convert(amount)),
+        separated_pair(Decimal::parse, whitespace, UnitOfMeasure::parse)
+            .map(|(amount, unit)| Quantity { amount, unit }),
+    ))
+    .terminated(whitespace.peek())
+    .context("spaced quantity")
+    .parse(input)
+}
+
+impl FromGMD for Portion {
+    /// units are tried before serving names
+    #[tracing::instrument(skip(input), ret(level = Level::TRACE))]
+    fn parse(input: &str) -> Res<'_, Self> {
+        alt((
+            Quantity::parse.map(Self::from),
+            spaced_quantity.map(Self::from),
+            separated_pair(Decimal::parse, whitespace, serving_name)
+                .map(|(count, serving)| Self::Servings { count, serving }),
+        ))
+        .context(type_name!())
+        .parse(input)
+    }
+}
+
+impl Eat {
+    /// `12:30 <keyword> 2 slice of Chleb`, with an optional time
+    fn parse_after<'a, K>(
+        keyword: impl Parser<&'a str, K, ErrorTree<&'a str>>,
+    ) -> impl Parser<&'a str, Self, ErrorTree<&'a str>> {
+        tuple((
+            NaiveTime::parse.terminated(whitespace).opt(),
+            separated_pair(
+                Portion::parse,
+                keyword::OF::tag.pipe(surrounded_by_whitespace),
+                ProductName::parse,
+            )
+            .preceded_by(keyword.terminated(whitespace)),
+        ))
+        .map(|(time, (portion, product))| Self {
+            time,
+            portion,
+            product,
+        })
+    }
+}
+
 impl FromGMD for Eat {
     #[tracing::instrument(skip(input), ret(level = Level::TRACE))]
     fn parse(input: &str) -> Res<'_, Self> {
-        AmountOf::<ProductName>::parse
-            .preceded_by(keyword::EAT::tag.terminated(whitespace))
+        Self::parse_after(keyword::EAT::tag)
+            .context(type_name!())
+            .parse(input)
+    }
+}
+
+impl FromGMD for Uneat {
+    #[tracing::instrument(skip(input), ret(level = Level::TRACE))]
+    fn parse(input: &str) -> Res<'_, Self> {
+        Eat::parse_after(keyword::UNEAT::tag)
             .map(Self)
             .context(type_name!())
             .parse(input)
     }
 }
 
+impl FromGMD for Meal {
+    #[tracing::instrument(skip(input), ret(level = Level::TRACE))]
+    fn parse(input: &str) -> Res<'_, Self> {
+        tuple((
+            NaiveTime::parse.terminated(whitespace).opt(),
+            take_while1(|c: char| c != '\n').preceded_by(keyword::MEAL::tag.terminated(whitespace)),
+        ))
+        .map(|(time, name): (_, &str)| Self {
+            time,
+            name: name.trim_end().to_string(),
+        })
+        .context(type_name!())
+        .parse(input)
+    }
+}
+
+impl FromGMD for Target {
+    #[tracing::instrument(skip(input), ret(level = Level::TRACE))]
+    fn parse(input: &str) -> Res<'_, Self> {
+        separated_pair(
+            tuple((
+                Quantity::parse,
+                Quantity::parse.preceded_by(keyword::RANGE::tag).opt(),
+            )),
+            keyword::OF::tag.pipe(surrounded_by_whitespace),
+            ProductName::parse,
+        )
+        .preceded_by(keyword::TARGET::tag.terminated(whitespace))
+        .map_res(|((lower, upper), product)| Target::new(lower, upper, product))
+        .context(type_name!())
+        .parse(input)
+    }
+}
+
 impl FromGMD for LogEntry {
     #[tracing::instrument(skip(input), ret(level = Level::TRACE))]
     fn parse(input: &str) -> Res<'_, Self> {
         alt((
             Eat::parse.map(LogEntry::from),
+            Uneat::parse.map(LogEntry::from),
+            Meal::parse.map(LogEntry::from),
+            Target::parse.map(LogEntry::from),
             ProductDefinition::parse.map(LogEntry::from),
+            Nutrient::parse.map(LogEntry::from),
+            Include::parse.map(LogEntry::from),
             StartDay::parse.map(LogEntry::from),
         ))
         .context(type_name!())
@@ -262,11 +616,435 @@ impl FromGMD for LogEntry {
 }
 
 impl FromGMD for GMDLog {
+    /// an empty log has no entries
     #[tracing::instrument(skip(input), ret(level = Level::TRACE))]
     fn parse(input: &str) -> Res<'_, Self> {
-        separated_list1(whitespace, LogEntry::parse)
-            .map(Self)
-            .parse(input)
+        alt((
+            eof.map(|_| vec![]),
+            collect_separated_terminated(LogEntry::parse, whitespace, eof),
+        ))
+        .map(Self)
+        .context(type_name!())
+        .parse(input)
+    }
+}
+
+impl SpannedGMDLog {
+    /// an empty log has no entries
+    pub fn from_gmd(file: FileId, input: &str) -> Result<Self> {
+        alt((
+            eof.map(|_| vec![]),
+            collect_separated_terminated(LogEntry::parse.with_recognized(), whitespace, eof),
+        ))
+        .context("GMDLog")
+        .complete()
+        .parse(input.trim())
+        .map_err(|e| ParseDiagnostic::report(input, e))
+        .map(|(_, entries): (_, Vec<_>)| {
+            entries
+                .into_iter()
+                .map(|(fragment, inner)| Spanned {
+                    span: Span::of(file, input, fragment),
+                    inner,
+                })
+                .collect::<Vec<_>>()
+                .pipe(Self)
+        })
+        .with_context(|| format!("Parsing [{}]", type_name!()))
+    }
+
+    /// lines a log entry can start with - used to find a place to resume parsing after an error
+    fn starts_entry(line: &str) -> bool {
+        keyword::DEFINE::tag(line).is_ok()
+            || keyword::NUTRIENT::tag(line).is_ok()
+            || keyword::INCLUDE::tag(line).is_ok()
+            || keyword::EAT::tag(line).is_ok()
+            || keyword::UNEAT::tag(line).is_ok()
+            || keyword::MEAL::tag(line).is_ok()
+            || keyword::TARGET::tag(line).is_ok()
+            || line.starts_with(|c: char| c.is_ascii_digit())
+    }
+
+    /// everything after the line `input` starts on, up to the next line that looks like an entry,
+    /// indented or not
+    fn skip_to_next_entry(input: &str) -> &str {
+        input
+            .match_indices('\n')
+            .map(|(index, _)| &input[index + 1..])
+            .find(|line| Self::starts_entry(line.trim_start()))
+            .unwrap_or_default()
+    }
+
+    /// unlike [`SpannedGMDLog::from_gmd`] doesn't stop at the first malformed entry, but skips
+    /// to the next line starting with `define`, `eat` or a date, returning every entry it could
+    /// parse along with a diagnostic for each one it couldn't
+    pub fn from_gmd_recovering(file: FileId, input: &str) -> (Self, Vec<ParseDiagnostic>) {
+        let mut entries = vec![];
+        let mut diagnostics = vec![];
+        let mut rest = input.trim_start();
+        while !rest.is_empty() {
+            match LogEntry::parse
+                .with_recognized()
+                .terminated(alt((whitespace, eof.map(|_| ()))))
+                .context("GMDLog")
+                .parse(rest)
+            {
+                Ok((tail, (fragment, inner))) => {
+                    entries.push(Spanned {
+                        span: Span::of(file, input, fragment),
+                        inner,
+                    });
+                    rest = tail;
+                }
+                Err(nom::Err::Error(e) | nom::Err::Failure(e)) => {
+                    diagnostics.push(ParseDiagnostic::new(input, &e));
+                    rest = Self::skip_to_next_entry(rest);
+                }
+                Err(nom::Err::Incomplete(_)) => break,
+            }
+            rest = rest.trim_start();
+        }
+        (Self(entries), diagnostics)
+    }
+}
+
+impl ToGMD for Gram {
+    fn to_gmd(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl ToGMD for Kcal {
+    fn to_gmd(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl ToGMD for Kilojoule {
+    fn to_gmd(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl ToGMD for Millilitre {
+    fn to_gmd(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl ToGMD for Percent {
+    fn to_gmd(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl ToGMD for Kilogram {
+    fn to_gmd(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl ToGMD for Ounce {
+    fn to_gmd(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl ToGMD for Pound {
+    fn to_gmd(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl ToGMD for Litre {
+    fn to_gmd(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl ToGMD for Cup {
+    fn to_gmd(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl ToGMD for Tablespoon {
+    fn to_gmd(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl ToGMD for Teaspoon {
+    fn to_gmd(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl ToGMD for UnitOfMeasure {
+    fn to_gmd(&self) -> String {
+        match self {
+            UnitOfMeasure::Gram(gram) => gram.to_gmd(),
+            UnitOfMeasure::Kcal(kcal) => kcal.to_gmd(),
+            UnitOfMeasure::Kilojoule(kilojoule) => kilojoule.to_gmd(),
+            UnitOfMeasure::Millilitre(millilitre) => millilitre.to_gmd(),
+            UnitOfMeasure::Percent(percent) => percent.to_gmd(),
+            UnitOfMeasure::Kilogram(kilogram) => kilogram.to_gmd(),
+            UnitOfMeasure::Ounce(ounce) => ounce.to_gmd(),
+            UnitOfMeasure::Pound(pound) => pound.to_gmd(),
+            UnitOfMeasure::Litre(litre) => litre.to_gmd(),
+            UnitOfMeasure::Cup(cup) => cup.to_gmd(),
+            UnitOfMeasure::Tablespoon(tablespoon) => tablespoon.to_gmd(),
+            UnitOfMeasure::Teaspoon(teaspoon) => teaspoon.to_gmd(),
+        }
+    }
+}
+
+impl ToGMD for Decimal {
+    fn to_gmd(&self) -> String {
+        self.normalize().to_string()
+    }
+}
+
+impl ToGMD for Quantity {
+    fn to_gmd(&self) -> String {
+        self.pipe_ref(|Self { amount, unit }| {
+            match unit {
+                UnitOfMeasure::Gram(_) => SpecialUnitOfMeasure::for_grams(*amount).map(|special| {
+                    format!(
+                        "{}{}",
+                        special.convert_grams(*amount).to_gmd(),
+                        special.to_gmd()
+                    )
+                }),
+                _ => None,
+            }
+            .unwrap_or_else(|| format!("{}{}", amount.to_gmd(), unit.to_gmd()))
+        })
+    }
+}
+
+impl<T: ToGMD> ToGMD for AmountOf<T> {
+    fn to_gmd(&self) -> String {
+        self.pipe_ref(|Self { quantity, inner }| {
+            format!("{} {} {}", quantity.to_gmd(), keyword::OF, inner.to_gmd())
+        })
+    }
+}
+
+impl ToGMD for NaiveDate {
+    fn to_gmd(&self) -> String {
+        self.format("%Y-%m-%d").to_string()
+    }
+}
+
+impl ToGMD for NaiveTime {
+    fn to_gmd(&self) -> String {
+        self.format("%H:%M").to_string()
+    }
+}
+
+impl ToGMD for StartDay {
+    fn to_gmd(&self) -> String {
+        self.0.to_gmd()
+    }
+}
+
+impl ToGMD for ProductName {
+    fn to_gmd(&self) -> String {
+        self.0.clone()
+    }
+}
+
+impl ToGMD for Density {
+    fn to_gmd(&self) -> String {
+        format!(
+            "{} {} {} {}",
+            keyword::DENSITY,
+            self.volume.to_gmd(),
+            keyword::EQUALS,
+            self.mass.to_gmd()
+        )
+    }
+}
+
+impl ToGMD for ProductDefinition {
+    fn to_gmd(&self) -> String {
+        self.pipe_ref(
+            |Self {
+                 name,
+                 ingredients:
+                     AmountOf {
+                         quantity,
+                         inner: ingredients,
+                     },
+                 density,
+                 servings,
+             }| {
+                std::iter::once(format!(
+                    "{} {}",
+                    keyword::DEFINE,
+                    quantity.of(name.clone()).to_gmd()
+                ))
+                .chain(
+                    ingredients
+                        .iter()
+                        .flatten()
+                        .map(|ingredient| format!(" - {}", ingredient.to_gmd())),
+                )
+                .chain(
+                    density
+                        .iter()
+                        .map(|density| format!(" {}", density.to_gmd())),
+                )
+                .chain(
+                    servings
+                        .iter()
+                        .map(|serving| format!(" {}", serving.to_gmd())),
+                )
+                .collect::<Vec<_>>()
+                .join("\n")
+            },
+        )
+    }
+}
+
+impl ToGMD for Nutrient {
+    fn to_gmd(&self) -> String {
+        format!("{} {}", keyword::NUTRIENT, self.0.to_gmd())
+    }
+}
+
+impl ToGMD for Include {
+    fn to_gmd(&self) -> String {
+        format!(
+            "{} {}{}{}",
+            keyword::INCLUDE,
+            keyword::QUOTE,
+            self.0.display(),
+            keyword::QUOTE
+        )
+    }
+}
+
+impl ToGMD for Serving {
+    fn to_gmd(&self) -> String {
+        format!(
+            "{} {} {} {}",
+            keyword::SERVING,
+            self.name,
+            keyword::EQUALS,
+            self.quantity.to_gmd()
+        )
+    }
+}
+
+impl ToGMD for Portion {
+    fn to_gmd(&self) -> String {
+        match self {
+            Portion::Quantity(quantity) => quantity.to_gmd(),
+            Portion::Servings { count, serving } => format!("{} {serving}", count.to_gmd()),
+        }
+    }
+}
+
+/// `12:30 <line>`
+fn at_time(time: Option<NaiveTime>, line: String) -> String {
+    match time {
+        Some(time) => format!("{} {line}", time.to_gmd()),
+        None => line,
+    }
+}
+
+impl Eat {
+    fn to_gmd_after(&self, keyword: impl std::fmt::Display) -> String {
+        format!(
+            "{keyword} {} {} {}",
+            self.portion.to_gmd(),
+            keyword::OF,
+            self.product.to_gmd()
+        )
+        .pipe(|line| at_time(self.time, line))
+    }
+}
+
+impl ToGMD for Eat {
+    fn to_gmd(&self) -> String {
+        self.to_gmd_after(keyword::EAT)
+    }
+}
+
+impl ToGMD for Uneat {
+    fn to_gmd(&self) -> String {
+        self.0.to_gmd_after(keyword::UNEAT)
+    }
+}
+
+impl ToGMD for Meal {
+    fn to_gmd(&self) -> String {
+        at_time(self.time, format!("{} {}", keyword::MEAL, self.name))
+    }
+}
+
+impl ToGMD for Target {
+    fn to_gmd(&self) -> String {
+        format!(
+            "{} {}{} {} {}",
+            keyword::TARGET,
+            self.lower.to_gmd(),
+            self.upper
+                .map(|upper| format!("{}{}", keyword::RANGE, upper.to_gmd()))
+                .unwrap_or_default(),
+            keyword::OF,
+            self.product.to_gmd()
+        )
+    }
+}
+
+impl ToGMD for LogEntry {
+    fn to_gmd(&self) -> String {
+        match self {
+            LogEntry::StartDay(start_day) => start_day.to_gmd(),
+            LogEntry::Include(include) => include.to_gmd(),
+            LogEntry::Define(product) => product.to_gmd(),
+            LogEntry::Nutrient(nutrient) => nutrient.to_gmd(),
+            LogEntry::Eat(eat) => eat.to_gmd(),
+            LogEntry::Uneat(uneat) => uneat.to_gmd(),
+            LogEntry::Meal(meal) => meal.to_gmd(),
+            LogEntry::Target(target) => target.to_gmd(),
+        }
+    }
+}
+
+impl LogEntry {
+    /// days and multi-line definitions read better with an empty line around them,
+    /// meals with one before them unless they open the day
+    fn needs_empty_line_before(&self, previous: &Self) -> bool {
+        let is_multiline = |entry: &Self| entry.to_gmd().contains('\n');
+        match (self, previous) {
+            (LogEntry::StartDay(_), _) => true,
+            (LogEntry::Meal(_), previous) => !matches!(previous, LogEntry::StartDay(_)),
+            _ => is_multiline(self) || is_multiline(previous),
+        }
+    }
+}
+
+impl ToGMD for GMDLog {
+    fn to_gmd(&self) -> String {
+        self.0
+            .iter()
+            .enumerate()
+            .fold(String::new(), |acc, (index, entry)| {
+                acc.tap_mut(|acc| {
+                    if let Some(previous) = index.checked_sub(1).and_then(|i| self.0.get(i)) {
+                        acc.push('\n');
+                        if entry.needs_empty_line_before(previous) {
+                            acc.push('\n');
+                        }
+                    }
+                    acc.push_str(&entry.to_gmd());
+                })
+            })
+            .tap_mut(|out| out.push('\n'))
     }
 }
 
@@ -281,6 +1059,7 @@ mod tests {
         assert_eq!(UnitOfMeasure::from_gmd("g")?, UnitOfMeasure::Gram(Gram));
         assert!(UnitOfMeasure::from_gmd("e").is_err());
         assert!(UnitOfMeasure::from_gmd(" ").is_err());
+        assert!(UnitOfMeasure::from_gmd("%").is_err());
         Ok(())
     }
 
@@ -305,4 +1084,189 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn test_to_gmd_round_trip() -> Result<()> {
+        const EXAMPLE: &str = include_str!("../../../example.gmd");
+
+        let log = GMDLog::from_gmd(EXAMPLE)?;
+        let printed = log.to_gmd();
+        assert_eq!(GMDLog::from_gmd(&printed)?, log);
+        assert_eq!(GMDLog::from_gmd(&printed)?.to_gmd(), printed);
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_gmd() -> Result<()> {
+        assert_eq!(Quantity::from_gmd("10.50g")?.to_gmd(), "10.5g");
+        assert_eq!(Quantity::from_gmd("5mg")?.to_gmd(), "5mg");
+        assert_eq!(Quantity::from_gmd("0.019mg")?.to_gmd(), "19µg");
+        assert_eq!(Quantity::from_gmd("0.93g")?.to_gmd(), "0.93g");
+        assert_eq!(Quantity::from_gmd("0.058mg")?.to_gmd(), "58µg");
+        assert_eq!(Quantity::from_gmd("0g")?.to_gmd(), "0g");
+        assert_eq!(
+            ProductDefinition::from_gmd(
+                "define 100g of marchew\n - 41kcal of Wartość energetyczna\n  -   1.50g of Białko"
+            )?
+            .to_gmd(),
+            "define 100g of marchew\n - 41kcal of Wartość energetyczna\n - 1.5g of Białko"
+        );
+        assert_eq!(
+            Eat::from_gmd("eat   400g of Moaburger Avocadus")?.to_gmd(),
+            "eat 400g of Moaburger Avocadus"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_diagnostic() -> Result<()> {
+        const INPUT: &str = "2024-01-20\ndefine 1g of Białko\n\neat 40xg of Białko\n";
+
+        let diagnostic = GMDLog::from_gmd(INPUT)
+            .expect_err("unit is malformed")
+            .downcast::<ParseDiagnostic>()?;
+        assert_eq!((diagnostic.line, diagnostic.column), (4, 7));
+        assert!(diagnostic.expected.contains(&r#""kcal""#.to_string()));
+        assert_eq!(
+            GMDLog::from_gmd("2024-01-20\neat 40g fo Białko")
+                .expect_err("keyword is misspelled")
+                .downcast::<ParseDiagnostic>()?
+                .to_string(),
+            [
+                r#"expected "of" while parsing GMDLog > LogEntry > Eat at line 2, column 9"#,
+                "  |",
+                "2 | eat 40g fo Białko",
+                "  |         ^^",
+            ]
+            .join("\n")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_gmd_recovering() -> Result<()> {
+        const INPUT: &str = "2024-01-20\ndefine 100g of X\n - 5g of Y\n - 5qg of Z\neat 10g of X\neta 5g of X\n eat 5g of X\n2024-01-21\neat 1x of X\neat 2g of X\n";
+
+        let (log, diagnostics) = SpannedGMDLog::from_gmd_recovering(FileId(0), INPUT);
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|diagnostic| diagnostic.line)
+                .collect::<Vec<_>>(),
+            vec![4, 6, 9]
+        );
+        assert_eq!(
+            log.0
+                .iter()
+                .map(|entry| entry.span.line)
+                .collect::<Vec<_>>(),
+            vec![1, 2, 5, 7, 8, 10]
+        );
+        assert_eq!(
+            log.0
+                .iter()
+                .map(|entry| entry.inner.to_gmd())
+                .collect::<Vec<_>>(),
+            vec![
+                "2024-01-20",
+                "define 100g of X\n - 5g of Y",
+                "eat 10g of X",
+                "eat 5g of X",
+                "2024-01-21",
+                "eat 2g of X"
+            ]
+        );
+        assert_eq!(
+            SpannedGMDLog::from_gmd_recovering(FileId(0), "").0,
+            SpannedGMDLog(vec![])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_spanned_log() -> Result<()> {
+        const INPUT: &str = "\n2024-01-20\ndefine 100g of X\n - 5g of Y\n\neat 10g of X\n";
+
+        let log = SpannedGMDLog::from_gmd(FileId(3), INPUT)?;
+        assert_eq!(
+            log.0
+                .iter()
+                .map(|Spanned { span, .. }| (span.line, &INPUT[span.range.clone()]))
+                .collect::<Vec<_>>(),
+            vec![
+                (2, "2024-01-20"),
+                (3, "define 100g of X\n - 5g of Y"),
+                (6, "eat 10g of X")
+            ]
+        );
+        assert_eq!(GMDLog::from(log), GMDLog::from_gmd(INPUT)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_mass_and_volume_units() -> Result<()> {
+        let ml = |amount| Quantity {
+            amount,
+            unit: Millilitre.into(),
+        };
+        let g = |amount| Quantity {
+            amount,
+            unit: Gram.into(),
+        };
+        let in_base = |quantity: &str| Quantity::from_gmd(quantity).map(Quantity::to_base);
+        assert_eq!(in_base("1.5kg")?, g(dec!(1500)));
+        assert_eq!(in_base("2oz")?, g(dec!(56.69904625)));
+        assert_eq!(in_base("1lb")?, g(dec!(453.59237)));
+        assert_eq!(in_base("250ml")?, ml(dec!(250)));
+        assert_eq!(in_base("0.5l")?, ml(dec!(500)));
+        assert_eq!(in_base("1cup")?, ml(dec!(250)));
+        assert_eq!(in_base("2tbsp")?, ml(dec!(30)));
+        assert_eq!(in_base("1tsp")?, ml(dec!(5)));
+        assert_eq!(
+            Quantity::from_gmd("1lb")?.convert_to(Kilogram.into()),
+            Some(Quantity::from_gmd("0.45359237kg")?)
+        );
+        assert_eq!(Quantity::from_gmd("1cup")?.convert_to(Gram.into()), None);
+        let mut sum = Quantity::from_gmd("1lb")?;
+        sum.try_add(Quantity::from_gmd("100g")?)?;
+        assert_eq!(sum, g(dec!(553.59237)));
+        ["1.5kg", "2oz", "1lb", "0.5l", "1cup", "2tbsp", "1tsp"]
+            .into_iter()
+            .try_for_each(|quantity| {
+                Quantity::from_gmd(quantity).map(|parsed| assert_eq!(parsed.to_gmd(), quantity))
+            })?;
+        assert_eq!(
+            Eat::from_gmd("eat 1lb of Mleko")?.to_gmd(),
+            "eat 1lb of Mleko"
+        );
+        assert_eq!(
+            Density::from_gmd("density 1.03g = 1ml")?,
+            Density::from_gmd("density 1ml = 1.03g")?
+        );
+        assert_eq!(
+            Density::from_gmd("density 1cup = 240g")?.to_gmd(),
+            "density 1cup = 240g"
+        );
+        assert_eq!(
+            Density::from_gmd("density 1cup = 240g")?
+                .convert(Quantity::from_gmd("0.5cup")?, Gram.into()),
+            Some(g(dec!(120)))
+        );
+        assert!(Density::from_gmd("density 1ml = 1kcal").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_kilojoules() -> Result<()> {
+        let mut energy = Quantity::from_gmd("100kcal")?;
+        energy.try_add(Quantity::from_gmd("418.4kJ")?)?;
+        assert_eq!(energy, Quantity::from_gmd("200kcal")?);
+        assert_eq!(
+            energy.convert_to(Kilojoule.into()),
+            Some(Quantity::from_gmd("836.8kJ")?)
+        );
+        assert!(energy.try_add(Quantity::from_gmd("1g")?).is_err());
+        assert_eq!(Quantity::from_gmd("1.5kJ")?.to_gmd(), "1.5kJ");
+        Ok(())
+    }
 }
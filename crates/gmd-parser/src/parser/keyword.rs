@@ -61,4 +61,14 @@ keyword!(MINUS, "-");
 keyword!(PERCENT, "%");
 keyword!(OF, "of");
 keyword!(DEFINE, "define");
+keyword!(NUTRIENT, "nutrient");
+keyword!(INCLUDE, "include");
+keyword!(QUOTE, "\"");
 keyword!(EAT, "eat");
+keyword!(UNEAT, "uneat");
+keyword!(MEAL, "meal");
+keyword!(TARGET, "target");
+keyword!(RANGE, "..");
+keyword!(DENSITY, "density");
+keyword!(EQUALS, "=");
+keyword!(SERVING, "serving");
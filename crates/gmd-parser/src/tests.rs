@@ -1,5 +1,18 @@
-use crate::{models::GMDLog, parser::FromGMD};
+use crate::{
+    calculator::{
+        DefinitionCycle, FlattenErrors, GMDSummary, StrictMode, SummaryOptions, TargetStatus,
+    },
+    merge::{merge, DefinitionConflicts},
+    models::{
+        FileId, GMDLog, Gram, Include, Kcal, LogEntry, ProductDefinition, ProductName, Quantity,
+        SpannedGMDLog,
+    },
+    parser::{FromGMD, ToGMD},
+    workspace::{Source, Workspace},
+};
+use chrono::NaiveDate;
 use eyre::Result;
+use rust_decimal_macros::dec;
 
 #[test]
 #[ignore]
@@ -17,3 +30,824 @@ fn test_parses_example_1() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_format_is_idempotent() -> Result<()> {
+    const EXAMPLE: &str = include_str!("../../../example.gmd");
+
+    let formatted = crate::formatter::format(EXAMPLE)?;
+    assert!(crate::formatter::is_formatted(&formatted)?);
+    Ok(())
+}
+
+#[test]
+fn test_format_normalizes_layout() -> Result<()> {
+    const MESSY: &str = "2024-01-20\ndefine 1kcal of Energia\ndefine 100.00g of Jogurt\n-  95kcal of Energia\n\n\n\neat 150g of Jogurt\n2024-01-21\neat 0.05g of Jogurt";
+    const FORMATTED: &str = "2024-01-20\ndefine 1kcal of Energia\n\ndefine 100g of Jogurt\n - 95kcal of Energia\n\neat 150g of Jogurt\n\n2024-01-21\neat 50mg of Jogurt\n";
+
+    assert!(!crate::formatter::is_formatted(MESSY)?);
+    assert_eq!(crate::formatter::format(MESSY)?, FORMATTED);
+    Ok(())
+}
+
+#[test]
+fn test_format_crlf() -> Result<()> {
+    const CRLF: &str =
+        "2024-01-20\r\nnutrient Białko \r\n18:00 meal Kolacja\r\neat 1g of Białko\r\n";
+    const FORMATTED: &str = "2024-01-20\nnutrient Białko\n\n18:00 meal Kolacja\neat 1g of Białko\n";
+
+    let formatted = crate::formatter::format(CRLF)?;
+    assert_eq!(formatted, FORMATTED);
+    assert!(crate::formatter::is_formatted(&formatted)?);
+    assert_eq!(GMDLog::from_gmd(&formatted)?, GMDLog::from_gmd(CRLF)?);
+    Ok(())
+}
+
+#[test]
+fn test_volume_through_density() -> Result<()> {
+    const LOG: &str = "2024-01-20
+define 1g of Białko
+define 100g of Mleko
+ - 3.4g of Białko
+ density 1ml = 1.03g
+eat 1cup of Mleko
+";
+
+    let log = GMDLog::from_gmd(LOG)?;
+    let summary = GMDSummary::from_log(&log)?;
+    assert_eq!(
+        summary
+            .0
+            .get(&NaiveDate::from_ymd_opt(2024, 1, 20).unwrap())
+            .and_then(|day| day.state.get(&ProductName::new("Białko")))
+            .map(|quantity| quantity.amount),
+        Some(dec!(8.755))
+    );
+    assert_eq!(GMDLog::from_gmd(&log.to_gmd())?, log);
+    assert_eq!(
+        ProductDefinition::from_gmd(
+            "define 100g of Mleko\n - 3.4g of Białko\n serving glass = 250ml\n density 1ml = 1.03g"
+        )?,
+        ProductDefinition::from_gmd(
+            "define 100g of Mleko\n - 3.4g of Białko\n density 1ml = 1.03g\n serving glass = 250ml"
+        )?
+    );
+    assert!(ProductDefinition::from_gmd(
+        "define 100g of Mleko\n - 3.4g of Białko\n density 1ml = 1.03g\n density 1ml = 1g"
+    )
+    .is_err());
+    Ok(())
+}
+
+#[test]
+fn test_named_servings() -> Result<()> {
+    const LOG: &str = "2024-01-20
+define 1g of Białko
+define 100g of Chleb
+ - 9g of Białko
+ serving slice = 35g
+ serving loaf = 0.5kg
+eat 2 slice of Chleb
+eat 0.5 loaf of Chleb
+";
+
+    let log = GMDLog::from_gmd(LOG)?;
+    let summary = GMDSummary::from_log(&log)?;
+    assert_eq!(
+        summary
+            .0
+            .get(&NaiveDate::from_ymd_opt(2024, 1, 20).unwrap())
+            .and_then(|day| day.state.get(&ProductName::new("Białko")))
+            .map(|quantity| quantity.amount),
+        Some(dec!(28.8))
+    );
+    assert_eq!(GMDLog::from_gmd(&log.to_gmd())?, log);
+    assert!(
+        GMDLog::from_gmd("2024-01-20\ndefine 1g of Chleb\neat 1 piece of Chleb")
+            .and_then(|log| GMDSummary::from_log(&log).map(drop))
+            .is_err()
+    );
+    assert_eq!(
+        GMDLog::from_gmd("2024-01-20\neat 0.5 lb of Chleb\neat 2 mg of Chleb")?,
+        GMDLog::from_gmd("2024-01-20\neat 0.5lb of Chleb\neat 2mg of Chleb")?
+    );
+    assert!(GMDLog::from_gmd("2024-01-20\ndefine 1g of Chleb\n serving lb = 450g").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_percent_ingredients() -> Result<()> {
+    const LOG: &str = "2024-01-20
+define 1g of Białko
+define 1g of Tłuszcz
+define 200g of Twaróg
+ - 12% of Białko
+ - 8g of Tłuszcz
+eat 50g of Twaróg
+";
+
+    let log = GMDLog::from_gmd(LOG)?;
+    let summary = GMDSummary::from_log(&log)?;
+    let day = summary
+        .0
+        .get(&NaiveDate::from_ymd_opt(2024, 1, 20).unwrap())
+        .unwrap();
+    assert_eq!(
+        day.state
+            .get(&ProductName::new("Białko"))
+            .map(|quantity| quantity.amount),
+        Some(dec!(6))
+    );
+    assert_eq!(
+        day.state
+            .get(&ProductName::new("Tłuszcz"))
+            .map(|quantity| quantity.amount),
+        Some(dec!(2))
+    );
+    assert_eq!(GMDLog::from_gmd(&log.to_gmd())?, log);
+    [
+        "eat 50% of Twaróg",
+        "uneat 50% of Twaróg",
+        "target 10% of Białko",
+        "define 100% of Twaróg\n - 12g of Białko",
+        "define 100g of Twaróg\n serving half = 50%",
+    ]
+    .into_iter()
+    .for_each(|entry| assert!(LogEntry::from_gmd(entry).is_err(), "{entry}"));
+    Ok(())
+}
+
+#[test]
+fn test_corrections_and_removed_ingredients() -> Result<()> {
+    const LOG: &str = "2024-01-20
+define 1g of Białko
+define 1g of Tłuszcz
+define 100g of Kanapka
+ - 10g of Białko
+ - 5g of Tłuszcz
+define 100g of Kanapka bez masła
+ - 100g of Kanapka
+ - -5g of Tłuszcz
+eat 200g of Kanapka
+uneat 100g of Kanapka
+eat -50g of Kanapka
+eat 100g of Kanapka bez masła
+";
+
+    let log = GMDLog::from_gmd(LOG)?;
+    let summary = GMDSummary::from_log(&log)?;
+    let day = summary
+        .0
+        .get(&NaiveDate::from_ymd_opt(2024, 1, 20).unwrap())
+        .unwrap();
+    assert_eq!(
+        day.state
+            .get(&ProductName::new("Białko"))
+            .map(|quantity| quantity.amount),
+        Some(dec!(15))
+    );
+    assert_eq!(
+        day.state
+            .get(&ProductName::new("Tłuszcz"))
+            .map(|quantity| quantity.amount),
+        Some(dec!(2.5))
+    );
+    assert_eq!(GMDLog::from_gmd(&log.to_gmd())?, log);
+    assert!(log.to_gmd().contains("\nuneat 100g of Kanapka\n"));
+    Ok(())
+}
+
+#[test]
+fn test_meals() -> Result<()> {
+    const LOG: &str = "2024-01-20
+define 1g of Białko
+
+define 100g of Jajko
+ - 12g of Białko
+
+07:30 meal Śniadanie
+eat 100g of Jajko
+
+meal Kolacja
+eat 50g of Jajko
+
+meal Śniadanie
+eat 50g of Jajko
+";
+
+    let log = GMDLog::from_gmd(LOG)?;
+    assert_eq!(log.to_gmd(), LOG);
+    let summary = GMDSummary::from_log(&log)?;
+    let day = summary
+        .0
+        .get(&NaiveDate::from_ymd_opt(2024, 1, 20).unwrap())
+        .unwrap();
+    assert_eq!(
+        day.meals
+            .iter()
+            .map(|meal| (
+                meal.meal.name.as_str(),
+                meal.state
+                    .get(&ProductName::new("Białko"))
+                    .map(|quantity| quantity.amount)
+            ))
+            .collect::<Vec<_>>(),
+        vec![("Śniadanie", Some(dec!(18))), ("Kolacja", Some(dec!(6)))]
+    );
+    assert_eq!(
+        day.state
+            .get(&ProductName::new("Białko"))
+            .map(|quantity| quantity.amount),
+        Some(dec!(24))
+    );
+    Ok(())
+}
+
+#[test]
+fn test_timeline() -> Result<()> {
+    const LOG: &str = "2024-01-20
+define 1g of Białko
+
+define 100g of Jajko
+ - 12g of Białko
+
+18:00 eat 100g of Jajko
+07:30 eat 50g of Jajko
+eat 50g of Jajko
+12:00 uneat 50g of Jajko
+";
+
+    let log = GMDLog::from_gmd(LOG)?;
+    assert_eq!(log.to_gmd(), LOG);
+    let summary = GMDSummary::from_log(&log)?;
+    let day = summary
+        .0
+        .get(&NaiveDate::from_ymd_opt(2024, 1, 20).unwrap())
+        .unwrap();
+    assert_eq!(
+        day.timeline()?
+            .into_iter()
+            .map(|(eaten, total)| (
+                eaten.time.map(|time| time.to_string()),
+                total
+                    .get(&ProductName::new("Białko"))
+                    .map(|quantity| quantity.amount)
+            ))
+            .collect::<Vec<_>>(),
+        vec![
+            (Some("07:30:00".into()), Some(dec!(6))),
+            (None, Some(dec!(12))),
+            (Some("12:00:00".into()), Some(dec!(6))),
+            (Some("18:00:00".into()), Some(dec!(18))),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_timeline_meal_times() -> Result<()> {
+    const LOG: &str = "2024-01-20
+nutrient Białko
+
+18:00 meal Kolacja
+eat 20g of Białko
+
+08:00 meal Śniadanie
+eat 10g of Białko
+09:00 eat 5g of Białko
+";
+
+    let log = GMDLog::from_gmd(LOG)?;
+    let summary = GMDSummary::from_log(&log)?;
+    assert_eq!(
+        summary.0[&NaiveDate::from_ymd_opt(2024, 1, 20).unwrap()]
+            .timeline()?
+            .into_iter()
+            .map(|(eaten, total)| (
+                eaten.time.map(|time| time.to_string()),
+                total
+                    .get(&ProductName::new("Białko"))
+                    .map(|quantity| quantity.amount)
+            ))
+            .collect::<Vec<_>>(),
+        vec![
+            (Some("08:00:00".into()), Some(dec!(10))),
+            (Some("09:00:00".into()), Some(dec!(15))),
+            (Some("18:00:00".into()), Some(dec!(35))),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_targets() -> Result<()> {
+    const LOG: &str = "2024-01-20
+define 1kcal of Energia
+define 1g of Białko
+target 2000kcal of Energia
+target 100g..150g of Białko
+eat 1000kcal of Energia
+eat 160g of Białko
+
+2024-01-21
+target 8368kJ of Energia
+eat 2000kcal of Energia
+eat 120g of Białko
+";
+
+    let log = GMDLog::from_gmd(LOG)?;
+    assert_eq!(GMDLog::from_gmd(&log.to_gmd())?, log);
+    let summary = GMDSummary::from_log(&log)?;
+    let status = |day: u32| -> Result<Vec<_>> {
+        summary
+            .progress(NaiveDate::from_ymd_opt(2024, 1, day).unwrap())
+            .map(|progress| {
+                progress
+                    .into_iter()
+                    .map(|progress| (progress.target.product.to_string(), progress.status))
+                    .collect()
+            })
+    };
+    let g = |amount| Quantity {
+        amount,
+        unit: Gram.into(),
+    };
+    assert_eq!(
+        status(20)?,
+        vec![
+            ("Białko".into(), TargetStatus::Surplus(g(dec!(10)))),
+            (
+                "Energia".into(),
+                TargetStatus::Deficit(Quantity {
+                    amount: dec!(1000),
+                    unit: Kcal.into()
+                })
+            ),
+        ]
+    );
+    assert_eq!(
+        status(21)?,
+        vec![
+            ("Białko".into(), TargetStatus::Met),
+            ("Energia".into(), TargetStatus::Met),
+        ]
+    );
+    assert!(GMDLog::from_gmd("target 200g..100g of Białko").is_err());
+    assert!(GMDLog::from_gmd("target -5g of Białko").is_err());
+    assert!(GMDLog::from_gmd("target 0g..100g of Białko").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_definition_versions() -> Result<()> {
+    const LOG: &str = "2024-01-20
+define 1g of Białko
+
+define 1g of Cukier
+
+define 100g of Jogurt
+ - 10g of Białko
+ - 5g of Cukier
+eat 100g of Jogurt
+
+2024-01-21
+eat 100g of Jogurt
+
+2024-01-22
+define 200g of Jogurt
+ - 20g of Białko
+
+define 200g of Jogurt
+ - 21g of Białko
+
+2024-01-23
+eat 100g of Jogurt
+
+2024-01-24
+define 100g of Deser
+ - 50g of Jogurt
+
+eat 100g of Deser
+";
+
+    let log = SpannedGMDLog::from_gmd(FileId(0), LOG)?;
+    let summary = GMDSummary::from_spanned_log(&log)?;
+    let day = |day| NaiveDate::from_ymd_opt(2024, 1, day).unwrap();
+    let versions = summary.versions(&ProductName::new("Jogurt"));
+    assert_eq!(
+        versions
+            .iter()
+            .map(|version| (
+                version.span.map(|span| span.line),
+                version.effective_from,
+                version.effective_until,
+                version.used_on.clone()
+            ))
+            .collect::<Vec<_>>(),
+        vec![
+            (
+                Some(6),
+                Some(day(20)),
+                Some(day(22)),
+                vec![day(20), day(21)]
+            ),
+            (Some(15), Some(day(22)), Some(day(22)), vec![]),
+            (Some(18), Some(day(22)), None, vec![day(23), day(24)]),
+        ]
+    );
+    let diff = versions[0].definition.diff(versions[2].definition)?;
+    assert_eq!(
+        diff.0
+            .iter()
+            .map(|change| (change.product.to_string(), change.relative()))
+            .collect::<Vec<_>>(),
+        vec![
+            ("Białko".to_string(), Some(dec!(0.05))),
+            ("Cukier".to_string(), None),
+        ]
+    );
+    assert!(diff.exceeds(dec!(0.1)));
+    assert!(!diff.0[0].exceeds(dec!(0.1)));
+
+    const EMPTY: &str = "2024-01-20
+nutrient Białko
+define 0g of Pusty
+ - 0g of Białko
+define 100g of Pusty
+ - 1g of Białko
+";
+    let empty = GMDLog::from_gmd(EMPTY)?;
+    assert!(GMDSummary::from_log(&empty).is_ok());
+    assert!(Quantity::from_gmd("1g")?
+        .ratio(Quantity::from_gmd("0g")?)
+        .is_err());
+    Ok(())
+}
+
+#[test]
+fn test_definition_cycles() -> Result<()> {
+    const LOG: &str = "2024-01-20
+define 1g of Białko
+
+define 100g of A
+ - 50g of B
+ - 5g of Białko
+
+define 100g of B
+ - 50g of A
+
+define 100g of C
+ - 10g of C
+eat 100g of A
+";
+
+    let log = GMDLog::from_gmd(LOG)?;
+    assert_eq!(
+        log.definition_cycles()
+            .iter()
+            .map(|cycle| cycle.to_string())
+            .collect::<Vec<_>>(),
+        vec!["definition cycle: B -> A -> B", "definition cycle: C -> C"]
+    );
+    assert_eq!(
+        GMDSummary::from_log(&log)
+            .expect_err("A contains itself through B")
+            .downcast::<DefinitionCycle>()?,
+        DefinitionCycle(["A", "B", "A"].into_iter().map(ProductName::new).collect())
+    );
+
+    // every product of a layer is made of both products of the layer below it,
+    // which makes for 2^40 distinct paths from the top
+    let layered = (0..40)
+        .rev()
+        .flat_map(|layer| {
+            ["a", "b"].map(|side| {
+                format!(
+                    "define 100g of L{layer}{side}\n - 50g of L{0}a\n - 50g of L{0}b\n",
+                    layer + 1
+                )
+            })
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    assert!(GMDLog::from_gmd(&layered)?.definition_cycles().is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_strict_mode() -> Result<()> {
+    const LOG: &str = "2024-01-20
+nutrient Białko
+
+define 100g of Burger
+ - 20g of Białko
+ - 10g of Sos
+
+nutrient Energia
+
+define 100kcal of Napój
+ - 100kcal of Energia
+
+define 100g of Zestaw
+ - 100g of Burger
+ - 10g of Napój
+eat 100g of Burger
+eat 100g of Zestaw
+";
+
+    let log = SpannedGMDLog::from_gmd(FileId(0), LOG)?;
+    assert!(GMDSummary::from_spanned_log(&log).is_ok());
+    let errors = GMDSummary::from_spanned_log_with(
+        &log,
+        SummaryOptions {
+            strict: StrictMode::Strict,
+            ..Default::default()
+        },
+    )
+    .expect_err("Sos is not defined and Napój is measured in kcal")
+    .downcast::<FlattenErrors>()?;
+    assert_eq!(
+        errors
+            .0
+            .iter()
+            .map(|(span, error)| (span.as_ref().map(|span| span.line), error.to_string()))
+            .collect::<Vec<_>>(),
+        vec![
+            (
+                Some(4),
+                "ingredient [Sos] of [Burger] is not defined".into()
+            ),
+            (
+                Some(10),
+                "[10g] of [Napój] can't be compared with [100kcal] it's defined for".into()
+            ),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_nutrients() -> Result<()> {
+    const LOG: &str = "2024-01-20
+nutrient Białko
+define 100g of Chleb
+
+define 100g of Kanapka
+ - 10g of Białko
+ - 50g of Chleb
+
+eat 200g of Kanapka
+eat 3g of Białko
+";
+
+    let log = GMDLog::from_gmd(LOG)?;
+    assert_eq!(log.to_gmd(), LOG);
+    let summary = GMDSummary::from_log(&log)?;
+    let day = NaiveDate::from_ymd_opt(2024, 1, 20).unwrap();
+    let g = |amount| Quantity {
+        amount,
+        unit: Gram.into(),
+    };
+    let bialko = ProductName::new("Białko");
+    let chleb = ProductName::new("Chleb");
+    assert_eq!(
+        summary.nutrients().into_iter().collect::<Vec<_>>(),
+        vec![&bialko]
+    );
+    assert_eq!(summary.0[&day].state.get(&bialko), Some(&g(dec!(23))));
+    assert_eq!(
+        summary
+            .unknown_composition(day)
+            .into_iter()
+            .collect::<Vec<_>>(),
+        vec![(&chleb, g(dec!(100)))]
+    );
+    let errors = GMDSummary::from_log_with(
+        &log,
+        SummaryOptions {
+            strict: StrictMode::Strict,
+            ..Default::default()
+        },
+    )
+    .expect_err("Chleb is neither a nutrient nor broken down into one")
+    .downcast::<FlattenErrors>()?;
+    assert_eq!(
+        errors
+            .0
+            .iter()
+            .map(|(_, error)| error.to_string())
+            .collect::<Vec<_>>(),
+        vec!["composition of [Chleb] is not known"]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_include() -> Result<()> {
+    const LOG: &str = r#"include "foods/vegetables.gmd"
+
+2024-01-20
+eat 200g of Marchew
+"#;
+
+    let log = GMDLog::from_gmd(LOG)?;
+    assert_eq!(
+        log.0.first(),
+        Some(&LogEntry::Include(Include("foods/vegetables.gmd".into())))
+    );
+    assert_eq!(log.to_gmd(), LOG);
+    assert!(Include::from_gmd(r#"include "unterminated"#).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_library() -> Result<()> {
+    const LOG: &str = "nutrient Białko
+target 50g..100g of Białko
+
+define 100g of Jogurt
+ - 10g of Białko
+
+2024-01-20
+eat 100g of Jogurt
+
+2024-01-21
+define 100g of Jogurt
+ - 5g of Białko
+
+eat 100g of Jogurt
+";
+
+    let log = GMDLog::from_gmd(LOG)?;
+    let summary = GMDSummary::from_log(&log)?;
+    let day = |day| NaiveDate::from_ymd_opt(2024, 1, day).unwrap();
+    let bialko = |day| {
+        summary
+            .0
+            .get(&day)
+            .and_then(|day| day.state.get(&ProductName::new("Białko")))
+            .map(|quantity| quantity.amount)
+    };
+    assert_eq!(bialko(day(20)), Some(dec!(10)));
+    assert_eq!(bialko(day(21)), Some(dec!(5)));
+    assert_eq!(summary.library().defined_products.len(), 1);
+    assert_eq!(summary.targets(day(20)).len(), 1);
+    assert_eq!(
+        summary
+            .versions(&ProductName::new("Jogurt"))
+            .iter()
+            .map(|version| (version.effective_from, version.used_on.clone()))
+            .collect::<Vec<_>>(),
+        vec![(None, vec![day(20)]), (Some(day(21)), vec![day(21)])]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_undated_entries() -> Result<()> {
+    const LOG: &str = "nutrient Białko
+
+define 100g of Jogurt
+ - 10g of Białko
+
+eat 100g of Jogurt
+
+2024-01-20
+eat 50g of Jogurt
+";
+
+    let log = GMDLog::from_gmd(LOG)?;
+    assert!(GMDSummary::from_log(&log).is_err());
+    let day = |day| NaiveDate::from_ymd_opt(2024, 1, day).unwrap();
+    let summary = GMDSummary::from_log_with(
+        &log,
+        SummaryOptions {
+            reference_date: Some(day(19)),
+            ..Default::default()
+        },
+    )?;
+    assert_eq!(
+        summary
+            .0
+            .iter()
+            .map(|(day, summary)| (*day, summary.state[&ProductName::new("Białko")].amount))
+            .collect::<Vec<_>>(),
+        vec![(day(19), dec!(10)), (day(20), dec!(5))]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_merge() -> Result<()> {
+    const FOODS: &str = "nutrient Białko
+
+2024-01-21
+define 100g of Jogurt
+ - 5g of Białko
+";
+    const DIARY: &str = "2024-01-20
+define 100g of Jogurt
+ - 10g of Białko
+
+eat 100g of Jogurt
+
+2024-01-22
+eat 100g of Jogurt
+";
+    const CONFLICTING: &str = "2024-01-21
+define 100g of Jogurt
+ - 6g of Białko
+";
+
+    let parse = |file, source| SpannedGMDLog::from_gmd(FileId(file), source);
+    let log = merge([parse(0, DIARY)?, parse(1, FOODS)?])?;
+    assert_eq!(
+        log.0
+            .iter()
+            .map(|entry| (entry.span.file.0, entry.span.line))
+            .collect::<Vec<_>>(),
+        vec![
+            (1, 1),
+            (0, 1),
+            (0, 2),
+            (0, 5),
+            (1, 3),
+            (1, 4),
+            (0, 7),
+            (0, 8)
+        ]
+    );
+    let summary = GMDSummary::from_spanned_log(&log)?;
+    let day = NaiveDate::from_ymd_opt(2024, 1, 22).unwrap();
+    assert_eq!(
+        summary.0[&day].state[&ProductName::new("Białko")].amount,
+        dec!(5)
+    );
+
+    let conflicts = merge([parse(0, DIARY)?, parse(1, FOODS)?, parse(2, CONFLICTING)?])
+        .expect_err("Jogurt is defined differently on 2024-01-21")
+        .downcast::<DefinitionConflicts>()?;
+    assert_eq!(
+        conflicts.to_string(),
+        "1 conflicting definition(s)
+file #1, line 4, file #2, line 2: [Jogurt] is defined differently on 2024-01-21"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_workspace() -> Result<()> {
+    let source = |name: &str, contents: &str| Source::Memory {
+        name: name.into(),
+        contents: contents.into(),
+    };
+    let workspace = Workspace::load([
+        source("january.gmd", "2024-01-20\neat 100g of Jogurt\n"),
+        source(
+            "foods.gmd",
+            "nutrient Białko\n\ndefine 100g of Jogurt\n - 10g of Białko\n",
+        ),
+    ])?;
+    assert_eq!(workspace.gmd_log().0.len(), 4);
+    let summary = workspace.summary(Default::default())?;
+    assert_eq!(
+        summary.0[&NaiveDate::from_ymd_opt(2024, 1, 20).unwrap()].state
+            [&ProductName::new("Białko")]
+            .amount,
+        dec!(10)
+    );
+
+    let workspace = Workspace::load([source("january.gmd", "2024-01-20\neat 1g of Mleko\n")])?;
+    let error = workspace
+        .summary(Default::default())
+        .expect_err("Mleko is not defined");
+    assert_eq!(
+        error
+            .chain()
+            .map(|error| error.to_string())
+            .collect::<Vec<_>>(),
+        vec![
+            "in 'january.gmd:2'",
+            r#"product [ProductNameKind("Mleko")] is not defined"#
+        ]
+    );
+
+    let workspace = Workspace::load([source(
+        "january.gmd",
+        "nutrient Białko\n\n2024-01-20\ndefine 100g of Jogurt\n - 10g of Mleko\neat 1g of Jogurt",
+    )])?;
+    let error = workspace
+        .summary(SummaryOptions {
+            strict: StrictMode::Strict,
+            ..Default::default()
+        })
+        .expect_err("Mleko is not defined");
+    assert_eq!(
+        error.to_string(),
+        concat!(
+            "1 product(s) could not be flattened\n",
+            "january.gmd:4: ingredient [Mleko] of [Jogurt] is not defined"
+        )
+    );
+    Ok(())
+}
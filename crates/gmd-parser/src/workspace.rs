@@ -0,0 +1,435 @@
+use crate::{
+    calculator::{FlattenErrors, GMDSummary, SummaryOptions},
+    error::{Located, ParseDiagnostic},
+    merge::{merge, DefinitionConflicts},
+    models::{FileId, GMDLog, Include, LogEntry, Span, SpannedGMDLog},
+};
+use eyre::{eyre, Result, WrapErr};
+use itertools::Itertools;
+use std::{
+    collections::BTreeSet,
+    iter::once,
+    path::{Component, Path, PathBuf},
+};
+use tap::prelude::*;
+
+/// where logs are loaded from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// a file, or a directory searched for `*.gmd` files
+    Path(PathBuf),
+    /// see [`glob`]
+    Glob(String),
+    /// a log that isn't read from disk, reported as `name` and including files relative to it
+    Memory { name: PathBuf, contents: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceFile {
+    /// as given, or joined onto the directory of the file including it
+    pub path: PathBuf,
+    pub contents: String,
+    /// `None` for in-memory sources.
+    /// files on disk are read once, however many times they're included
+    canonical: Option<PathBuf>,
+}
+
+/// every file a log was read from and the log merged from all of them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Workspace {
+    /// identified by their position, included files come before the ones including them
+    pub files: Vec<SourceFile>,
+    pub log: SpannedGMDLog,
+}
+
+/// `*` matches any number of characters and `?` a single one
+fn matches(pattern: &str, name: &str) -> bool {
+    fn rest(text: &str) -> Option<&str> {
+        text.chars().next().map(|c| &text[c.len_utf8()..])
+    }
+    match (pattern.chars().next(), name.chars().next()) {
+        (None, None) => true,
+        (Some('*'), _) => {
+            matches(&pattern[1..], name)
+                || rest(name)
+                    .map(|name| matches(pattern, name))
+                    .unwrap_or(false)
+        }
+        (Some('?'), Some(_)) => matches(&pattern[1..], rest(name).unwrap_or_default()),
+        (Some(expected), Some(found)) if expected == found => matches(
+            rest(pattern).unwrap_or_default(),
+            rest(name).unwrap_or_default(),
+        ),
+        _ => false,
+    }
+}
+
+/// entries of `directory`, nothing if it isn't one
+fn children(directory: &Path) -> Result<Vec<PathBuf>> {
+    match directory.as_os_str().is_empty() {
+        true => Path::new("."),
+        false => directory,
+    }
+    .pipe(|listed| match listed.is_dir() {
+        true => std::fs::read_dir(listed)
+            .and_then(|entries| {
+                entries
+                    .map(|entry| entry.map(|entry| directory.join(entry.file_name())))
+                    .collect::<std::io::Result<Vec<_>>>()
+            })
+            .with_context(|| format!("listing '{}'", listed.display())),
+        false => Ok(vec![]),
+    })
+}
+
+/// dotfiles such as `.git`, which wildcards only match when the pattern starts with a `.`
+fn hidden(path: &Path) -> bool {
+    path.file_name()
+        .map(|name| name.to_string_lossy().starts_with('.'))
+        .unwrap_or(false)
+}
+
+/// existing paths under `base` matching the rest of a pattern.
+/// `**` doesn't descend into symlinked directories, which could lead back to where it started
+fn expand(base: PathBuf, components: &[String]) -> Result<BTreeSet<PathBuf>> {
+    match components.split_first() {
+        None => base
+            .exists()
+            .then_some(base)
+            .into_iter()
+            .collect::<BTreeSet<_>>()
+            .pipe(Ok),
+        Some((component, rest)) if component == "**" => children(&base).and_then(|children| {
+            children
+                .into_iter()
+                .filter(|child| child.is_dir() && !child.is_symlink() && !hidden(child))
+                .map(|child| expand(child, components))
+                .chain(once(expand(base, rest)))
+                .try_fold(BTreeSet::new(), |all, matched| {
+                    matched.map(|matched| all.tap_mut(|all| all.extend(matched)))
+                })
+        }),
+        Some((component, rest)) if component.contains(['*', '?']) => {
+            children(&base).and_then(|children| {
+                children
+                    .into_iter()
+                    .filter(|child| component.starts_with('.') || !hidden(child))
+                    .filter(|child| {
+                        child
+                            .file_name()
+                            .map(|name| matches(component, &name.to_string_lossy()))
+                            .unwrap_or(false)
+                    })
+                    .map(|child| expand(child, rest))
+                    .try_fold(BTreeSet::new(), |all, matched| {
+                        matched.map(|matched| all.tap_mut(|all| all.extend(matched)))
+                    })
+            })
+        }
+        Some((component, rest)) => expand(base.join(component), rest),
+    }
+}
+
+/// paths matching `pattern`, in order. `*` and `?` match within a single directory or file name,
+/// `**` matches any number of directories
+pub fn glob(pattern: &str) -> Result<Vec<PathBuf>> {
+    Path::new(pattern)
+        .components()
+        .fold(
+            (PathBuf::new(), vec![]),
+            |(base, components), component| match component {
+                Component::Prefix(_) | Component::RootDir => (base.join(component), components),
+                component => (
+                    base,
+                    components.tap_mut(|components| {
+                        components.push(component.as_os_str().to_string_lossy().into_owned())
+                    }),
+                ),
+            },
+        )
+        .pipe(|(base, components)| expand(base, &components))
+        .with_context(|| format!("expanding '{pattern}'"))
+        .and_then(|matched| match matched.is_empty() {
+            true => Err(eyre!("nothing matches '{pattern}'")),
+            false => matched.into_iter().collect_vec().pipe(Ok),
+        })
+}
+
+impl Workspace {
+    /// reads the sources along with everything they include
+    pub fn read(sources: impl IntoIterator<Item = Source>) -> Result<Vec<SourceFile>> {
+        let mut files = vec![];
+        sources
+            .into_iter()
+            .try_for_each(|source| match source {
+                Source::Path(path) if path.is_dir() => {
+                    expand(path.clone(), &["**".into(), "*.gmd".into()])
+                        .with_context(|| format!("searching '{}'", path.display()))
+                        .and_then(|paths| {
+                            paths
+                                .iter()
+                                .filter(|path| path.is_file())
+                                .try_for_each(|path| Self::read_along(path, &[], &mut files))
+                        })
+                }
+                Source::Path(path) => Self::read_along(&path, &[], &mut files),
+                Source::Glob(pattern) => glob(&pattern).and_then(|paths| {
+                    paths
+                        .iter()
+                        .try_for_each(|path| Self::read_along(path, &[], &mut files))
+                }),
+                Source::Memory { name, contents } => {
+                    Self::read_includes(&name, &contents, &[], &mut files).map(|_| {
+                        files.push(SourceFile {
+                            path: name,
+                            contents,
+                            canonical: None,
+                        })
+                    })
+                }
+            })
+            .map(|_| files)
+    }
+
+    /// `including` leads from one of the sources to this file and is how include cycles are caught
+    fn read_along(path: &Path, including: &[PathBuf], files: &mut Vec<SourceFile>) -> Result<()> {
+        let canonical = path
+            .canonicalize()
+            .wrap_err("reading file")
+            .with_context(|| format!("reading '{}'", path.display()))?;
+        if including.contains(&canonical) {
+            return including
+                .iter()
+                .chain(once(&canonical))
+                .map(|path| path.display().to_string())
+                .join(" -> ")
+                .pipe(|cycle| Err(eyre!("include cycle: {cycle}")));
+        }
+        if files
+            .iter()
+            .any(|file| file.canonical.as_ref() == Some(&canonical))
+        {
+            return Ok(());
+        }
+        let contents = std::fs::read_to_string(&canonical)
+            .wrap_err("reading file")
+            .with_context(|| format!("reading '{}'", path.display()))?;
+        including
+            .to_vec()
+            .tap_mut(|including| including.push(canonical.clone()))
+            .pipe(|including| Self::read_includes(path, &contents, &including, files))
+            .map(|_| {
+                files.push(SourceFile {
+                    path: path.to_path_buf(),
+                    contents,
+                    canonical: Some(canonical),
+                })
+            })
+    }
+
+    /// reads the files included by `contents` of the file at `path`, malformed entries are left
+    /// for [`Workspace::load`] to report
+    fn read_includes(
+        path: &Path,
+        contents: &str,
+        including: &[PathBuf],
+        files: &mut Vec<SourceFile>,
+    ) -> Result<()> {
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        // the file isn't numbered yet, but only the entries are needed here
+        SpannedGMDLog::from_gmd_recovering(FileId(0), contents)
+            .0
+            .pipe(GMDLog::from)
+            .0
+            .into_iter()
+            .filter_map(|entry| match entry {
+                LogEntry::Include(include) => Some(include),
+                _ => None,
+            })
+            .try_for_each(|Include(included)| {
+                Self::read_along(&directory.join(included), including, files)
+            })
+    }
+
+    /// reads, parses and merges all the sources
+    pub fn load(sources: impl IntoIterator<Item = Source>) -> Result<Self> {
+        Self::read(sources).and_then(|files| {
+            files
+                .iter()
+                .enumerate()
+                .map(|(id, file)| {
+                    SpannedGMDLog::from_gmd(FileId(id), &file.contents)
+                        .wrap_err("parsing file")
+                        .with_context(|| format!("reading '{}'", file.path.display()))
+                })
+                .collect::<Result<Vec<_>>>()
+                .context("file(s) corrupted")
+                .and_then(|logs| Self::merged(files, logs))
+        })
+    }
+
+    /// like [`Workspace::load`], but skips malformed entries, returning what's wrong with them
+    pub fn load_recovering(
+        sources: impl IntoIterator<Item = Source>,
+    ) -> Result<(Self, Vec<(FileId, ParseDiagnostic)>)> {
+        Self::read(sources).and_then(|files| {
+            files
+                .iter()
+                .enumerate()
+                .map(|(id, file)| {
+                    SpannedGMDLog::from_gmd_recovering(FileId(id), &file.contents).pipe(
+                        |(log, diagnostics)| {
+                            (log, diagnostics.into_iter().map(move |d| (FileId(id), d)))
+                        },
+                    )
+                })
+                .fold((vec![], vec![]), |(logs, all), (log, diagnostics)| {
+                    (
+                        logs.tap_mut(|logs| logs.push(log)),
+                        all.tap_mut(|all| all.extend(diagnostics)),
+                    )
+                })
+                .pipe(|(logs, diagnostics)| {
+                    Self::merged(files, logs).map(|workspace| (workspace, diagnostics))
+                })
+        })
+    }
+
+    fn merged(files: Vec<SourceFile>, logs: Vec<SpannedGMDLog>) -> Result<Self> {
+        Self {
+            files,
+            log: SpannedGMDLog(vec![]),
+        }
+        .pipe(|workspace| match merge(logs) {
+            Ok(log) => Ok(Self { log, ..workspace }),
+            Err(report) => Err(workspace.locate(report)),
+        })
+    }
+
+    pub fn gmd_log(&self) -> GMDLog {
+        self.log.clone().into()
+    }
+
+    /// errors are located with [`Workspace::locate`]
+    pub fn summary(&self, options: SummaryOptions) -> Result<GMDSummary<'_>> {
+        GMDSummary::from_spanned_log_with(&self.log, options).map_err(|report| self.locate(report))
+    }
+
+    /// `path:line`
+    pub fn location(&self, Span { file, line, .. }: &Span) -> String {
+        self.files
+            .get(file.0)
+            .map(|source| format!("{}:{line}", source.path.display()))
+            .unwrap_or_else(|| format!("{file}:{line}"))
+    }
+
+    /// names the file and line an error comes from, if it is known, in place of its [`FileId`]
+    pub fn locate(&self, report: eyre::Report) -> eyre::Report {
+        match (
+            report.downcast_ref::<Span>(),
+            report.downcast_ref::<FlattenErrors>(),
+            report.downcast_ref::<DefinitionConflicts>(),
+        ) {
+            (Some(span), ..) => report
+                .chain()
+                .map(|error| error.to_string())
+                .map(|message| match message == span.to_string() {
+                    true => format!("in '{}'", self.location(span)),
+                    false => message,
+                })
+                .collect_vec()
+                .into_iter()
+                .rev()
+                .fold(None, |inner: Option<eyre::Report>, message| match inner {
+                    Some(inner) => Some(inner.wrap_err(message)),
+                    None => Some(eyre!(message)),
+                })
+                .unwrap_or(report),
+            (None, Some(errors), _) => eyre!(errors.located(|span| self.location(span))),
+            (None, None, Some(conflicts)) => {
+                eyre!(conflicts.located(|span| self.location(span)))
+            }
+            (None, None, None) => report,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{glob, matches, Source, Workspace};
+    use eyre::Result;
+    use std::iter::once;
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("*.gmd", "2024-01.gmd"));
+        assert!(matches("2024-0?.gmd", "2024-01.gmd"));
+        assert!(matches("*", ""));
+        assert!(!matches("*.gmd", "notes.txt"));
+        assert!(!matches("2024-0?.gmd", "2024-1.gmd"));
+        assert!(matches("ż*", "żurek"));
+    }
+
+    #[test]
+    fn test_directories_and_globs() -> Result<()> {
+        let root = std::env::temp_dir().join(format!("gmd-workspace-{}", std::process::id()));
+        [
+            "2024/02/diary.gmd",
+            "2024/01/diary.gmd",
+            "foods.gmd",
+            "notes.txt",
+        ]
+        .iter()
+        .map(|path| (*path, "nutrient Białko\n"))
+        .chain(once(("2024/03/diary.gmd", " \n\n")))
+        .chain(once((".git/diary.gmd", "nutrient Białko\n")))
+        .try_for_each(|(path, contents)| {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap_or(&root))
+                .and_then(|_| std::fs::write(path, contents))
+        })?;
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&root, root.join("2024/loop"))?;
+        let relative = |workspace: Workspace| {
+            workspace
+                .files
+                .into_iter()
+                .map(|file| file.path.strip_prefix(&root).unwrap().display().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            relative(Workspace::load([Source::Path(root.clone())])?),
+            vec![
+                "2024/01/diary.gmd",
+                "2024/02/diary.gmd",
+                "2024/03/diary.gmd",
+                "foods.gmd"
+            ]
+        );
+        assert_eq!(
+            relative(Workspace::load([Source::Glob(format!(
+                "{}/2024/*/diary.gmd",
+                root.display()
+            ))])?),
+            vec![
+                "2024/01/diary.gmd",
+                "2024/02/diary.gmd",
+                "2024/03/diary.gmd"
+            ]
+        );
+        assert!(glob(&format!("{}/*.toml", root.display())).is_err());
+        assert_eq!(
+            glob(&format!("{}/.*/*.gmd", root.display()))?,
+            vec![root.join(".git/diary.gmd")]
+        );
+        assert_eq!(
+            relative(Workspace::load([Source::Memory {
+                name: root.join("stdin"),
+                contents: "nutrient Jod\n  include \"foods.gmd\"\n".into(),
+            }])?),
+            vec!["foods.gmd", "stdin"]
+        );
+        std::fs::remove_dir_all(&root)?;
+        Ok(())
+    }
+}